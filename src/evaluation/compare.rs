@@ -0,0 +1,272 @@
+use super::stats;
+use super::table::{self, Row};
+use crate::misc::metrics::RUN_TIME;
+use anyhow::{bail, Result};
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt::Write;
+use std::path::PathBuf;
+
+/// Problem as domain and problem name
+type Problem<'a> = (&'a str, &'a str);
+
+/// Report comparing solver a against solver b in the results at path
+/// The first of attributes, by default the run time if it was measured,
+/// decides which solver is better on a problem both solve, where lower values
+/// are better unless maximize is given
+pub fn compare(
+    results: &PathBuf,
+    a: &str,
+    b: &str,
+    attributes: &[String],
+    maximize: bool,
+) -> Result<String> {
+    let table = table::read(results)?;
+    table.require(attributes)?;
+    let attributes = match attributes.is_empty() && table.columns.iter().any(|c| c == RUN_TIME) {
+        true => &[RUN_TIME.to_owned()],
+        false => attributes,
+    };
+    let rows_of = |solver: &str| -> Result<BTreeMap<Problem, &Row>> {
+        let rows: BTreeMap<Problem, &Row> = table
+            .rows
+            .iter()
+            .filter(|r| r.solver == solver)
+            .map(|r| ((r.domain.as_str(), r.problem.as_str()), r))
+            .collect();
+        if rows.is_empty() {
+            bail!(
+                "Results have no solver {}, available are: {}",
+                solver,
+                table.solvers().join(", ")
+            );
+        }
+        Ok(rows)
+    };
+    let a_rows = rows_of(a)?;
+    let b_rows = rows_of(b)?;
+    let problems: BTreeSet<Problem> = a_rows.keys().chain(b_rows.keys()).cloned().collect();
+    let solved =
+        |rows: &BTreeMap<Problem, &Row>, p: &Problem| rows.get(p).is_some_and(|r| r.solved());
+
+    let mut report = String::new();
+    writeln!(report, "Per problem")?;
+    let mut header = vec!["domain".to_owned(), "problem".to_owned()];
+    match attributes.is_empty() {
+        true => header.extend([a.to_owned(), b.to_owned()]),
+        false => {
+            for attribute in attributes.iter() {
+                header.push(format!("{} ({})", attribute, a));
+                header.push(format!("{} ({})", attribute, b));
+            }
+        }
+    }
+    let mut lines = vec![header];
+    for p in problems.iter() {
+        let mut line = vec![p.0.to_owned(), p.1.to_owned()];
+        let value = |rows: &BTreeMap<Problem, &Row>, attribute: Option<&String>| match rows.get(p) {
+            None => "n/a".to_owned(),
+            Some(row) if !row.solved() => "-".to_owned(),
+            Some(row) => match attribute {
+                None => "solved".to_owned(),
+                Some(attribute) => row.values.get(attribute).cloned().unwrap_or_default(),
+            },
+        };
+        match attributes.is_empty() {
+            true => line.extend([value(&a_rows, None), value(&b_rows, None)]),
+            false => {
+                for attribute in attributes.iter() {
+                    line.push(value(&a_rows, Some(attribute)));
+                    line.push(value(&b_rows, Some(attribute)));
+                }
+            }
+        }
+        lines.push(line);
+    }
+    report.push_str(&format_table(&lines));
+
+    for (solver, other, rows, other_rows) in [(a, b, &a_rows, &b_rows), (b, a, &b_rows, &a_rows)] {
+        let only: Vec<&Problem> = problems
+            .iter()
+            .filter(|p| solved(rows, p) && !solved(other_rows, p))
+            .collect();
+        writeln!(
+            report,
+            "\nSolved by {} but not {} ({})",
+            solver,
+            other,
+            only.len()
+        )?;
+        for p in only {
+            writeln!(report, "  {}/{}", p.0, p.1)?;
+        }
+    }
+
+    // Value of the deciding attribute on problems both solve, as a and b
+    let shared: Vec<(Problem, f64, f64)> = match attributes.first() {
+        None => vec![],
+        Some(attribute) => problems
+            .iter()
+            .filter(|p| solved(&a_rows, p) && solved(&b_rows, p))
+            .filter_map(|p| {
+                let a = a_rows[p].number(attribute)?;
+                let b = b_rows[p].number(attribute)?;
+                Some((*p, a, b))
+            })
+            .collect(),
+    };
+    let better = |x: f64, y: f64| match maximize {
+        true => x > y,
+        false => x < y,
+    };
+
+    writeln!(report, "\nPer domain")?;
+    let mut header: Vec<String> = vec![
+        "domain".to_owned(),
+        format!("solved ({})", a),
+        format!("solved ({})", b),
+        format!("only ({})", a),
+        format!("only ({})", b),
+    ];
+    if let Some(attribute) = attributes.first() {
+        header.extend([
+            format!("{} better ({})", attribute, a),
+            format!("{} better ({})", attribute, b),
+            "equal".to_owned(),
+        ]);
+    }
+    let mut lines = vec![header];
+    let domains: BTreeSet<&str> = problems.iter().map(|p| p.0).collect();
+    for domain in domains {
+        let in_domain: Vec<&Problem> = problems.iter().filter(|p| p.0 == domain).collect();
+        let count =
+            |f: &dyn Fn(&Problem) -> bool| in_domain.iter().filter(|p| f(p)).count().to_string();
+        let mut line = vec![
+            domain.to_owned(),
+            count(&|p| solved(&a_rows, p)),
+            count(&|p| solved(&b_rows, p)),
+            count(&|p| solved(&a_rows, p) && !solved(&b_rows, p)),
+            count(&|p| solved(&b_rows, p) && !solved(&a_rows, p)),
+        ];
+        if !attributes.is_empty() {
+            let shared: Vec<&(Problem, f64, f64)> =
+                shared.iter().filter(|s| s.0 .0 == domain).collect();
+            line.extend([
+                shared
+                    .iter()
+                    .filter(|s| better(s.1, s.2))
+                    .count()
+                    .to_string(),
+                shared
+                    .iter()
+                    .filter(|s| better(s.2, s.1))
+                    .count()
+                    .to_string(),
+                shared.iter().filter(|s| s.1 == s.2).count().to_string(),
+            ]);
+        }
+        lines.push(line);
+    }
+    report.push_str(&format_table(&lines));
+
+    if let Some(attribute) = attributes.first() {
+        writeln!(
+            report,
+            "\nWilcoxon signed-rank test on {} of problems solved by both",
+            attribute
+        )?;
+        let differences: Vec<f64> = shared.iter().map(|s| s.1 - s.2).collect();
+        match stats::wilcoxon(&differences) {
+            None => writeln!(report, "  No problems with differing values")?,
+            Some(test) => {
+                writeln!(
+                    report,
+                    "  n = {} ({} equal dropped), W+ = {}, W- = {}",
+                    test.n, test.zeros, test.w_plus, test.w_minus
+                )?;
+                writeln!(
+                    report,
+                    "  p = {} ({})",
+                    match test.p < 1e-4 {
+                        true => format!("{:.2e}", test.p),
+                        false => format!("{:.4}", test.p),
+                    },
+                    match test.exact {
+                        true => "exact",
+                        false => "normal approximation",
+                    }
+                )?;
+            }
+        }
+    }
+    if attributes.is_empty() {
+        writeln!(
+            report,
+            "\nNo attribute decides which solver is better, such that wins per domain and \
+             the Wilcoxon signed-rank test are left out"
+        )?;
+    }
+    Ok(report)
+}
+
+/// Aligns lines into columns, the first line being the header
+fn format_table(lines: &[Vec<String>]) -> String {
+    let columns = lines.iter().map(|l| l.len()).max().unwrap_or(0);
+    let widths: Vec<usize> = (0..columns)
+        .map(|c| {
+            lines
+                .iter()
+                .filter_map(|l| l.get(c))
+                .map(|v| v.len())
+                .max()
+                .unwrap_or(0)
+        })
+        .collect();
+    let mut table = String::new();
+    for line in lines.iter() {
+        let line: Vec<String> = line
+            .iter()
+            .enumerate()
+            .map(|(c, v)| format!("{:<width$}", v, width = widths[c]))
+            .collect();
+        table.push_str(line.join("  ").trim_end());
+        table.push('\n');
+    }
+    table
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    #[test]
+    fn run_time_decides_by_default() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let path = dir.path().join("solve.csv");
+        let mut content = "domain,problem,name,exit_code,outcome,run_time\n".to_owned();
+        for (p, a, b) in [(1, 1.0, 2.0), (2, 1.0, 3.0), (3, 4.0, 2.5)] {
+            content += &format!(
+                "d,p{0},a,0,finished,{1}\nd,p{0},b,0,finished,{2}\n",
+                p, a, b
+            );
+        }
+        fs::write(&path, &content)?;
+        let report = compare(&path, "a", "b", &[], false)?;
+        assert!(report.contains("run_time better (a)"), "{}", report);
+        assert!(report.contains("Wilcoxon signed-rank test on run_time"));
+
+        let without: String = content
+            .lines()
+            .map(|l| l.rsplit_once(',').unwrap().0.to_owned() + "\n")
+            .collect();
+        fs::write(&path, without)?;
+        let report = compare(&path, "a", "b", &[], false)?;
+        assert!(
+            !report.contains("Wilcoxon signed-rank test on"),
+            "{}",
+            report
+        );
+        assert!(report.contains("No attribute decides"));
+        Ok(())
+    }
+}
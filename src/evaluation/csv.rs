@@ -1,9 +1,19 @@
 use crate::setup::instance::Instance;
 use anyhow::Result;
 use log::trace;
-use std::{collections::HashSet, fs, path::PathBuf};
+use std::{
+    collections::HashSet,
+    fs,
+    io::Write,
+    path::{Path, PathBuf},
+};
 
-pub fn collect(out_dir: &PathBuf, instance: &Instance) -> Result<()> {
+/// Columns prepended to each row if provenance is given
+const PROVENANCE_COLUMNS: [&str; 4] = ["labyr_runner", "labyr_task", "labyr_problem", "labyr_run"];
+
+/// Concatenates the csv files of the same path in all run dirs into out_dir,
+/// prepending the run each row came from if provenance is given
+pub fn collect(out_dir: &Path, instance: &Instance, provenance: bool) -> Result<()> {
     let csvs: HashSet<PathBuf> = instance
         .runs
         .iter()
@@ -11,37 +21,71 @@ pub fn collect(out_dir: &PathBuf, instance: &Instance) -> Result<()> {
         .collect();
 
     for csv in csvs.iter() {
-        let mut content: Vec<String> = Default::default();
-        for run in instance.runs.iter() {
+        let csv_out = out_dir.join(csv);
+        if let Some(dir) = csv_out.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        let mut writer = csv::WriterBuilder::new()
+            .flexible(true)
+            .from_path(&csv_out)?;
+        let mut header = true;
+        for (i, run) in instance.runs.iter().enumerate() {
             let csv_loc = run.dir.join(csv);
             if !csv_loc.exists() {
                 continue;
             }
             trace!("Reading csv: {:?}", csv_loc);
-            let csv_content = fs::read_to_string(&csv_loc)?;
-            let lines: Vec<String> = csv_content.lines().map(|l| l.to_string()).collect();
-            if lines.is_empty() {
-                continue;
+            let dir = run
+                .dir
+                .strip_prefix(&instance.work_dir)
+                .unwrap_or(&run.dir)
+                .to_string_lossy();
+            let (columns, fields) = match provenance {
+                true => {
+                    let (runner, task, problem) = instance.run_key(i);
+                    (
+                        PROVENANCE_COLUMNS.to_vec(),
+                        vec![runner, task, problem.unwrap_or_default(), &dir],
+                    )
+                }
+                false => (vec![], vec![]),
+            };
+            if append(&mut writer, &csv_loc, &columns, &fields, header)? {
+                header = false;
             }
-            if content.is_empty() {
-                content.push(lines[0].to_owned());
-            }
-            for line in lines.into_iter().skip(1) {
-                content.push(line);
-            }
-        }
-        let csv_out = out_dir.join(csv);
-        if let Some(dir) = csv_out.parent() {
-            fs::create_dir_all(dir)?;
         }
-        fs::write(
-            &csv_out,
-            content.into_iter().collect::<Vec<String>>().join("\n"),
-        )?;
+        writer.flush()?;
     }
     Ok(())
 }
 
+/// Appends the records of the csv file at path to writer, each prefixed by
+/// fields, and its header prefixed by columns if header is set
+/// Returns whether the file has a header, false if it is empty
+fn append<W: Write>(
+    writer: &mut csv::Writer<W>,
+    path: &Path,
+    columns: &[&str],
+    fields: &[&str],
+    header: bool,
+) -> Result<bool> {
+    let mut reader = csv::ReaderBuilder::new()
+        .has_headers(false)
+        .flexible(true)
+        .from_path(path)?;
+    let mut records = reader.records();
+    let Some(first) = records.next() else {
+        return Ok(false);
+    };
+    if header {
+        writer.write_record(columns.iter().copied().chain(first?.iter()))?;
+    }
+    for record in records {
+        writer.write_record(fields.iter().copied().chain(record?.iter()))?;
+    }
+    Ok(true)
+}
+
 /// Recursively finds all files with extension
 fn find_files(dir: &PathBuf, ext: &str) -> Vec<PathBuf> {
     let mut files = vec![];
@@ -57,3 +101,36 @@ fn find_files(dir: &PathBuf, ext: &str) -> Vec<PathBuf> {
     }
     files
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fields_are_prepended_per_record() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let (a, b) = (dir.path().join("a.csv"), dir.path().join("b.csv"));
+        fs::write(&a, "plan,cost\r\n\"move a\nmove b\",2\r\n")?;
+        fs::write(&b, "plan,cost\n\"say \"\"hi\"\"\",1\n")?;
+        let mut writer = csv::Writer::from_writer(vec![]);
+        assert!(append(&mut writer, &a, &["run"], &["r,1"], true)?);
+        assert!(append(&mut writer, &b, &["run"], &["r2"], false)?);
+        let out = String::from_utf8(writer.into_inner()?)?;
+        assert_eq!(
+            out,
+            "run,plan,cost\n\"r,1\",\"move a\nmove b\",2\nr2,\"say \"\"hi\"\"\",1\n"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn empty_files_have_no_header() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let path = dir.path().join("empty.csv");
+        fs::write(&path, "")?;
+        let mut writer = csv::Writer::from_writer(vec![]);
+        assert!(!append(&mut writer, &path, &[], &[], true)?);
+        assert!(writer.into_inner()?.is_empty());
+        Ok(())
+    }
+}
@@ -0,0 +1,127 @@
+use super::{html, Outcome, Results};
+use crate::setup::instance::Instance;
+use anyhow::Result;
+use clap::ValueEnum;
+use std::collections::BTreeMap;
+use std::fs::{self, File};
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+/// Result formats written alongside learn.csv and solve.csv
+#[derive(Debug, Copy, Clone, PartialEq, Eq, ValueEnum)]
+pub enum Format {
+    /// results.json, an array of all runs
+    Json,
+    /// results.jsonl, one run per line
+    Jsonl,
+    /// results_long.csv, one row per run and variable
+    Long,
+    /// report.html, a static report linking to the files of each run
+    Html,
+}
+
+/// Result of a single run, as exported
+#[derive(serde::Serialize, Debug)]
+pub struct Record<'a> {
+    pub stage: &'a str,
+    pub domain: &'a str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub problem: Option<&'a str>,
+    pub runner: &'a str,
+    pub exit_code: Option<i32>,
+    pub outcome: Outcome,
+    /// Captured attribute values, leaving out those not captured
+    pub attributes: BTreeMap<&'a str, &'a str>,
+    pub metrics: &'a BTreeMap<String, String>,
+}
+
+fn attributes<'a>(names: &'a [String], values: &'a [String]) -> BTreeMap<&'a str, &'a str> {
+    names
+        .iter()
+        .zip(values.iter())
+        .filter(|(_, v)| !v.is_empty())
+        .map(|(n, v)| (n.as_str(), v.as_str()))
+        .collect()
+}
+
+/// Records of all runs in results, learners first
+pub fn records(results: &Results) -> Vec<Record<'_>> {
+    let learn = results.learn.iter().map(|row| Record {
+        stage: "learn",
+        domain: &row.domain,
+        problem: None,
+        runner: &row.learner,
+        exit_code: row.exit_code,
+        outcome: row.outcome,
+        attributes: attributes(&results.learn_attributes, &row.attributes),
+        metrics: &row.metrics,
+    });
+    let solve = results.solve.iter().map(|row| Record {
+        stage: "solve",
+        domain: &row.domain,
+        problem: Some(&row.problem),
+        runner: &row.solver,
+        exit_code: row.exit_code,
+        outcome: row.outcome,
+        attributes: attributes(&results.solve_attributes, &row.attributes),
+        metrics: &row.metrics,
+    });
+    learn.chain(solve).collect()
+}
+
+pub fn write(out_dir: &Path, instance: &Instance, results: &Results, format: Format) -> Result<()> {
+    let records = records(results);
+    match format {
+        Format::Json => {
+            fs::write(
+                out_dir.join("results.json"),
+                serde_json::to_string_pretty(&records)?,
+            )?;
+        }
+        Format::Jsonl => {
+            let mut file = BufWriter::new(File::create(out_dir.join("results.jsonl"))?);
+            for record in records.iter() {
+                writeln!(file, "{}", serde_json::to_string(record)?)?;
+            }
+            file.flush()?;
+        }
+        Format::Long => write_long(&out_dir.join("results_long.csv"), &records)?,
+        Format::Html => html::write(&out_dir.join("report.html"), instance, results)?,
+    }
+    Ok(())
+}
+
+/// Writes records in long format, where exit code, outcome, attributes and
+/// metrics each are a variable of their run
+fn write_long(path: &PathBuf, records: &[Record]) -> Result<()> {
+    let mut writer = ::csv::Writer::from_path(path)?;
+    writer.write_record(["stage", "domain", "problem", "runner", "variable", "value"])?;
+    for record in records.iter() {
+        let mut variables: Vec<(&str, String)> = vec![
+            (
+                "exit_code",
+                record.exit_code.map(|c| c.to_string()).unwrap_or_default(),
+            ),
+            ("outcome", record.outcome.to_string()),
+        ];
+        variables.extend(record.attributes.iter().map(|(n, v)| (*n, v.to_string())));
+        variables.extend(
+            record
+                .metrics
+                .iter()
+                .map(|(n, v)| (n.as_str(), v.to_owned())),
+        );
+        for (variable, value) in variables {
+            writer.write_record([
+                record.stage,
+                record.domain,
+                record.problem.unwrap_or_default(),
+                record.runner,
+                variable,
+                &value,
+            ])?;
+        }
+    }
+    writer.flush()?;
+    Ok(())
+}
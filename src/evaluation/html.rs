@@ -0,0 +1,250 @@
+use super::{escape_xml, metric_names, metric_values, Outcome, Results};
+use crate::misc::metrics::METRICS_FILE;
+use crate::setup::instance::{Instance, INTERRUPTED_FILE, SKIPPED_FILE};
+use anyhow::Result;
+use std::collections::BTreeSet;
+use std::fmt::Write;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Files of a run dir written by labyr rather than the runner
+const INTERNAL_FILES: [&str; 6] = [
+    "runner.sh",
+    "command",
+    "exit_code",
+    METRICS_FILE,
+    INTERRUPTED_FILE,
+    SKIPPED_FILE,
+];
+
+const STYLE: &str = "body{font-family:sans-serif;margin:2em}\
+table{border-collapse:collapse;margin:1em 0}\
+th,td{border:1px solid #ccc;padding:.2em .6em;text-align:left}\
+th{background:#eee}\
+tr.failed td{background:#fdd}\
+pre{background:#f6f6f6;padding:1em;overflow:auto}\
+summary{cursor:pointer;margin:.5em 0}";
+
+const SCRIPT: &str = "document.getElementById('failed').addEventListener('change',e=>{\
+document.querySelectorAll('tr.ok').forEach(r=>r.hidden=e.target.checked);\
+if(e.target.checked)document.querySelectorAll('details.domain').forEach(d=>d.open=true);});";
+
+/// Writes a self-contained report of results to path, linking to the files
+/// of each run in the work dir
+pub fn write(path: &PathBuf, instance: &Instance, results: &Results) -> Result<()> {
+    let mut html = String::new();
+    writeln!(html, "<!DOCTYPE html>")?;
+    writeln!(
+        html,
+        "<html><head><meta charset=\"utf-8\"><title>labyr report</title>"
+    )?;
+    writeln!(html, "<style>{}</style></head><body>", STYLE)?;
+    writeln!(html, "<h1>labyr report</h1>")?;
+    writeln!(
+        html,
+        "<p>Work dir: <code>{}</code>, links to run files require it to be kept</p>",
+        escape_xml(&instance.work_dir.to_string_lossy())
+    )?;
+    writeln!(html, "<h2>Suite</h2>")?;
+    writeln!(
+        html,
+        "<details><summary>Configuration</summary><pre>{}</pre></details>",
+        escape_xml(&instance.suite_source)
+    )?;
+
+    let domains: BTreeSet<&str> = results.solve.iter().map(|r| r.domain.as_str()).collect();
+    let mut solvers: Vec<&str> = vec![];
+    for row in results.solve.iter() {
+        if !solvers.contains(&row.solver.as_str()) {
+            solvers.push(&row.solver);
+        }
+    }
+    if !results.solve.is_empty() {
+        writeln!(html, "<h2>Coverage</h2>")?;
+        write!(html, "<table><tr><th>Domain</th>")?;
+        for solver in solvers.iter() {
+            write!(html, "<th>{}</th>", escape_xml(solver))?;
+        }
+        writeln!(html, "</tr>")?;
+        let coverage = |domain: Option<&str>, solver: &str| {
+            let rows: Vec<_> = results
+                .solve
+                .iter()
+                .filter(|r| r.solver == solver && domain.is_none_or(|d| r.domain == d))
+                .collect();
+            let solved = rows.iter().filter(|r| r.exit_code == Some(0)).count();
+            format!("{}/{}", solved, rows.len())
+        };
+        for domain in domains.iter().map(|d| Some(*d)).chain([None]) {
+            write!(
+                html,
+                "<tr><td>{}</td>",
+                escape_xml(domain.unwrap_or("Total"))
+            )?;
+            for solver in solvers.iter() {
+                write!(html, "<td>{}</td>", coverage(domain, solver))?;
+            }
+            writeln!(html, "</tr>")?;
+        }
+        writeln!(html, "</table>")?;
+    }
+
+    writeln!(
+        html,
+        "<h2>Runs</h2><label><input type=\"checkbox\" id=\"failed\"> Only failed runs</label>"
+    )?;
+    if !results.learn.is_empty() {
+        let metric_names = metric_names(results.learn.iter().map(|r| &r.metrics));
+        writeln!(
+            html,
+            "<details class=\"domain\"><summary>Learners</summary>"
+        )?;
+        let mut header = vec!["Domain".to_owned(), "Learner".to_owned()];
+        header.extend(status_columns());
+        header.extend(results.learn_attributes.iter().cloned());
+        header.extend(metric_names.iter().cloned());
+        header.push("Files".to_owned());
+        write_header(&mut html, &header)?;
+        for row in results.learn.iter() {
+            let mut cells = vec![escape_xml(&row.domain), escape_xml(&row.learner)];
+            cells.extend(status_cells(row.exit_code, row.outcome));
+            cells.extend(row.attributes.iter().map(|a| escape_xml(a)));
+            cells.extend(
+                metric_values(&metric_names, &row.metrics)
+                    .iter()
+                    .map(|m| escape_xml(m)),
+            );
+            cells.push(files(&row.dir));
+            write_row(&mut html, row.exit_code, &cells)?;
+        }
+        writeln!(html, "</table></details>")?;
+    }
+    let metric_names = metric_names(results.solve.iter().map(|r| &r.metrics));
+    for domain in domains.iter() {
+        let rows: Vec<_> = results
+            .solve
+            .iter()
+            .filter(|r| r.domain == *domain)
+            .collect();
+        let solved = rows.iter().filter(|r| r.exit_code == Some(0)).count();
+        writeln!(
+            html,
+            "<details class=\"domain\"><summary>{} &mdash; {}/{} runs solved</summary>",
+            escape_xml(domain),
+            solved,
+            rows.len()
+        )?;
+        let mut header = vec!["Problem".to_owned(), "Solver".to_owned()];
+        header.extend(status_columns());
+        header.extend(results.solve_attributes.iter().cloned());
+        header.extend(metric_names.iter().cloned());
+        header.push("Files".to_owned());
+        write_header(&mut html, &header)?;
+        for row in rows {
+            let mut cells = vec![escape_xml(&row.problem), escape_xml(&row.solver)];
+            cells.extend(status_cells(row.exit_code, row.outcome));
+            cells.extend(row.attributes.iter().map(|a| escape_xml(a)));
+            cells.extend(
+                metric_values(&metric_names, &row.metrics)
+                    .iter()
+                    .map(|m| escape_xml(m)),
+            );
+            cells.push(files(&row.dir));
+            write_row(&mut html, row.exit_code, &cells)?;
+        }
+        writeln!(html, "</table></details>")?;
+    }
+    writeln!(html, "<script>{}</script>", SCRIPT)?;
+    writeln!(html, "</body></html>")?;
+    fs::write(path, html)?;
+    Ok(())
+}
+
+fn status_columns() -> [String; 2] {
+    ["Exit code".to_owned(), "Outcome".to_owned()]
+}
+
+fn status_cells(exit_code: Option<i32>, outcome: Outcome) -> [String; 2] {
+    [
+        exit_code.map(|c| c.to_string()).unwrap_or_default(),
+        outcome.to_string(),
+    ]
+}
+
+fn write_header(html: &mut String, header: &[String]) -> Result<()> {
+    write!(html, "<table><tr>")?;
+    for column in header.iter() {
+        write!(html, "<th>{}</th>", escape_xml(column))?;
+    }
+    writeln!(html, "</tr>")?;
+    Ok(())
+}
+
+/// Writes a row of escaped cells, marked as failed unless the run succeeded
+fn write_row(html: &mut String, exit_code: Option<i32>, cells: &[String]) -> Result<()> {
+    let class = match exit_code {
+        Some(0) => "ok",
+        _ => "failed",
+    };
+    write!(html, "<tr class=\"{}\">", class)?;
+    for cell in cells.iter() {
+        write!(html, "<td>{}</td>", cell)?;
+    }
+    writeln!(html, "</tr>")?;
+    Ok(())
+}
+
+/// Links to the files written by the runner in dir, such as its log and plans
+fn files(dir: &PathBuf) -> String {
+    let mut names: Vec<String> = fs::read_dir(dir)
+        .map(|entries| {
+            entries
+                .flatten()
+                .filter(|e| e.path().is_file())
+                .map(|e| e.file_name().to_string_lossy().to_string())
+                .filter(|n| !INTERNAL_FILES.contains(&n.as_str()))
+                .collect()
+        })
+        .unwrap_or_default();
+    names.sort();
+    names
+        .iter()
+        .map(|n| {
+            format!(
+                "<a href=\"{}\">{}</a>",
+                file_url(&dir.join(n)),
+                escape_xml(n)
+            )
+        })
+        .collect::<Vec<String>>()
+        .join(" ")
+}
+
+/// URL of a local file, percent-encoding all but unreserved characters and
+/// separators, such that names with # or ? link to the whole name
+fn file_url(path: &Path) -> String {
+    let mut url = "file://".to_owned();
+    for byte in path.as_os_str().as_encoded_bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' | b'/' => {
+                url.push(*byte as char)
+            }
+            _ => url.push_str(&format!("%{:02X}", byte)),
+        }
+    }
+    url
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn file_urls_are_percent_encoded() {
+        assert_eq!(
+            file_url(Path::new("/tmp/run 1/plan#2?.txt")),
+            "file:///tmp/run%201/plan%232%3F.txt"
+        );
+        assert_eq!(file_url(Path::new("/tmp/ä")), "file:///tmp/%C3%A4");
+    }
+}
@@ -0,0 +1,211 @@
+use super::format_number;
+use super::stats::Aggregation;
+use super::table::{self, Row, Table};
+use anyhow::Result;
+use clap::ValueEnum;
+use std::collections::{BTreeSet, HashMap, HashSet};
+use std::fmt::Write;
+use std::path::PathBuf;
+
+/// An attribute summarized per domain and solver, given as
+/// NAME[:AGGREGATION[:max]], such as "expansions:geomean" or "quality:mean:max"
+#[derive(Debug, Clone)]
+pub struct Summary {
+    pub attribute: String,
+    pub aggregation: Aggregation,
+    /// Whether higher values are better
+    pub maximize: bool,
+}
+
+impl Summary {
+    pub fn parse(s: &str) -> Result<Summary, String> {
+        let mut parts = s.split(':');
+        let attribute = parts.next().unwrap_or_default().to_owned();
+        if attribute.is_empty() {
+            return Err("attribute name is empty".to_owned());
+        }
+        let aggregation = match parts.next() {
+            None => Aggregation::Mean,
+            Some(a) => Aggregation::from_str(a, true)?,
+        };
+        let maximize = match parts.next() {
+            None | Some("min") => false,
+            Some("max") => true,
+            Some(d) => return Err(format!("direction {} is neither min nor max", d)),
+        };
+        if parts.next().is_some() {
+            return Err(format!("{} has too many parts", s));
+        }
+        Ok(Summary {
+            attribute,
+            aggregation,
+            maximize,
+        })
+    }
+}
+
+/// Booktabs tables of the results at path, with domains as rows and solvers
+/// as columns. The first table is coverage, followed by one per summary over
+/// solved problems, or only those solved by all solvers if common is given
+pub fn latex(results: &PathBuf, summaries: &[Summary], common: bool) -> Result<String> {
+    let table = table::read(results)?;
+    table.require(
+        &summaries
+            .iter()
+            .map(|s| s.attribute.to_owned())
+            .collect::<Vec<String>>(),
+    )?;
+    let solvers = table.solvers();
+    let domains: BTreeSet<&str> = table.rows.iter().map(|r| r.domain.as_str()).collect();
+    let domains: Vec<&str> = domains.into_iter().collect();
+
+    let mut tex = String::new();
+    let coverage = |domain: Option<&str>, solver: &str| {
+        Some(
+            table
+                .rows
+                .iter()
+                .filter(|r| r.solver == solver && domain.is_none_or(|d| r.domain == d))
+                .filter(|r| r.solved())
+                .count() as f64,
+        )
+    };
+    write_table(&mut tex, "Coverage", &domains, &solvers, true, coverage)?;
+
+    let included = included(&table, &solvers, common);
+    for summary in summaries.iter() {
+        let value = |domain: Option<&str>, solver: &str| {
+            let values: Vec<f64> = table
+                .rows
+                .iter()
+                .filter(|r| r.solver == solver && domain.is_none_or(|d| r.domain == d))
+                .filter(|r| included(r))
+                .filter_map(|r| r.number(&summary.attribute))
+                .collect();
+            summary.aggregation.apply(&values)
+        };
+        let caption = format!(
+            "{} ({})",
+            summary.attribute,
+            summary.aggregation.to_possible_value().unwrap().get_name()
+        );
+        tex.push('\n');
+        write_table(
+            &mut tex,
+            &caption,
+            &domains,
+            &solvers,
+            summary.maximize,
+            value,
+        )?;
+    }
+    Ok(tex)
+}
+
+/// Whether a row counts towards summaries, being solved, and if common is
+/// given, solved by all solvers
+fn included<'a>(table: &'a Table, solvers: &[&str], common: bool) -> impl Fn(&Row) -> bool + 'a {
+    let mut solved_by: HashMap<(&str, &str), usize> = HashMap::new();
+    for row in table.rows.iter() {
+        *solved_by.entry((&row.domain, &row.problem)).or_default() += row.solved() as usize;
+    }
+    let unshared: HashSet<(&str, &str)> = match common {
+        true => solved_by
+            .into_iter()
+            .filter(|(_, n)| *n < solvers.len())
+            .map(|(p, _)| p)
+            .collect(),
+        false => HashSet::new(),
+    };
+    move |row: &Row| {
+        row.solved() && !unshared.contains(&(row.domain.as_str(), row.problem.as_str()))
+    }
+}
+
+/// Writes a table where value gives the cell of a domain, or all domains if
+/// None, and solver
+fn write_table(
+    tex: &mut String,
+    caption: &str,
+    domains: &[&str],
+    solvers: &[&str],
+    maximize: bool,
+    value: impl Fn(Option<&str>, &str) -> Option<f64>,
+) -> Result<()> {
+    writeln!(tex, "\\begin{{table}}")?;
+    writeln!(tex, "  \\centering")?;
+    writeln!(tex, "  \\caption{{{}}}", escape(caption))?;
+    writeln!(
+        tex,
+        "  \\begin{{tabular}}{{l{}}}",
+        "r".repeat(solvers.len())
+    )?;
+    writeln!(tex, "    \\toprule")?;
+    writeln!(
+        tex,
+        "    Domain & {} \\\\",
+        solvers
+            .iter()
+            .map(|s| escape(s))
+            .collect::<Vec<String>>()
+            .join(" & ")
+    )?;
+    writeln!(tex, "    \\midrule")?;
+    for domain in domains.iter() {
+        let values: Vec<Option<f64>> = solvers.iter().map(|s| value(Some(domain), s)).collect();
+        writeln!(
+            tex,
+            "    {} & {} \\\\",
+            escape(domain),
+            cells(&values, maximize)
+        )?;
+    }
+    writeln!(tex, "    \\midrule")?;
+    let values: Vec<Option<f64>> = solvers.iter().map(|s| value(None, s)).collect();
+    writeln!(tex, "    Total & {} \\\\", cells(&values, maximize))?;
+    writeln!(tex, "    \\bottomrule")?;
+    writeln!(tex, "  \\end{{tabular}}")?;
+    writeln!(tex, "\\end{{table}}")?;
+    Ok(())
+}
+
+/// Formats a row of values, with the best in bold
+fn cells(values: &[Option<f64>], maximize: bool) -> String {
+    let best = values
+        .iter()
+        .flatten()
+        .cloned()
+        .reduce(|a, b| match maximize {
+            true => a.max(b),
+            false => a.min(b),
+        });
+    values
+        .iter()
+        .map(|v| match v {
+            None => "--".to_owned(),
+            Some(v) if Some(*v) == best && values.iter().flatten().count() > 1 => {
+                format!("\\textbf{{{}}}", format_number(*v, 2))
+            }
+            Some(v) => format_number(*v, 2),
+        })
+        .collect::<Vec<String>>()
+        .join(" & ")
+}
+
+/// Escapes characters with special meaning in LaTeX
+fn escape(s: &str) -> String {
+    let mut escaped = String::new();
+    for c in s.chars() {
+        match c {
+            '&' | '%' | '$' | '#' | '_' | '{' | '}' => {
+                escaped.push('\\');
+                escaped.push(c);
+            }
+            '~' => escaped.push_str("\\textasciitilde{}"),
+            '^' => escaped.push_str("\\textasciicircum{}"),
+            '\\' => escaped.push_str("\\textbackslash{}"),
+            _ => escaped.push(c),
+        }
+    }
+    escaped
+}
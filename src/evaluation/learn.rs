@@ -1,13 +1,30 @@
-use super::{pattern_names, pattern_values};
+use super::{metric_names, metric_values, pattern_names, pattern_values, read_exit_code, Outcome};
+use crate::misc::metrics;
 use crate::setup::instance::{Instance, RunKind, Runner};
 use crate::setup::suite::{Attribute, RunnerKind};
 use anyhow::Result;
-use std::fs::{self, File};
-use std::io::Write;
-use std::path::PathBuf;
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
 
-pub fn collect(out_dir: &PathBuf, instance: &Instance) -> Result<()> {
-    let mut file = File::create(out_dir.join("learn.csv"))?;
+/// Outcome of a learner run
+#[derive(Debug, Clone)]
+pub struct LearnRow {
+    pub domain: String,
+    pub learner: String,
+    /// None if the learner did not finish
+    pub exit_code: Option<i32>,
+    /// How the run ended, telling apart why it has no exit code
+    pub outcome: Outcome,
+    /// Captured attribute values, ordered as the attribute names they belong to
+    pub attributes: Vec<String>,
+    /// Measurements recorded during the run
+    pub metrics: BTreeMap<String, String>,
+    pub dir: PathBuf,
+}
+
+/// Attribute names and learner rows of instance
+pub fn rows(instance: &Instance) -> (Vec<String>, Vec<LearnRow>) {
     let learn_runners = instance
         .runners
         .iter()
@@ -15,32 +32,65 @@ pub fn collect(out_dir: &PathBuf, instance: &Instance) -> Result<()> {
         .collect::<Vec<&Runner>>();
     let attributes = learn_runners
         .iter()
-        .filter_map(|r| match r.attribute {
-            Some(a) => Some(&instance.attributes[a]),
-            None => None,
-        })
+        .filter_map(|r| r.attribute.map(|a| &instance.attributes[a]))
         .collect::<Vec<&Attribute>>();
     let pattern_names = pattern_names(attributes);
-    let _ = file.write(b"domain,name,exit_code");
-    if !pattern_names.is_empty() {
-        let _ = file.write(format!(",{}", pattern_names.join(",")).as_bytes());
-    }
-    let _ = file.write(b"\n");
+    let mut rows = vec![];
     for run in instance.runs.iter().filter(|r| r.kind == RunKind::Learner) {
-        let learner = &instance.runners[run.runner_index].name;
-        let domain = &instance.tasks[run.task_index].name;
-        let exit_code = fs::read_to_string(run.dir.join("exit_code"))
-            .unwrap_or("404".to_string())
-            .trim()
-            .to_owned();
-        let _ = file.write(format!("{},{},{}", domain, learner, exit_code).as_bytes());
-        if let Some(attribute) = instance.runners[run.runner_index].attribute {
-            let content = fs::read_to_string(run.dir.join("log")).unwrap_or("".to_string());
-            let p_values =
-                pattern_values(&pattern_names, &instance.attributes[attribute], &content);
-            let _ = file.write(format!(",{}", p_values.join(",")).as_bytes());
-        }
-        let _ = file.write(b"\n");
+        let learner = instance.runners[run.runner_index].name.to_owned();
+        let domain = instance.tasks[run.task_index].name.to_owned();
+        let exit_code = read_exit_code(&run.dir);
+        let attributes = match instance.runners[run.runner_index].attribute {
+            Some(attribute) => {
+                let content = fs::read_to_string(run.dir.join("log")).unwrap_or("".to_string());
+                pattern_values(&pattern_names, &instance.attributes[attribute], &content)
+            }
+            None => vec!["".to_owned(); pattern_names.len()],
+        };
+        rows.push(LearnRow {
+            domain,
+            learner,
+            exit_code,
+            outcome: Outcome::of(&run.dir, exit_code),
+            attributes,
+            metrics: metrics::read(&run.dir),
+            dir: run.dir.to_owned(),
+        });
+    }
+    (
+        pattern_names.into_iter().map(|n| n.to_owned()).collect(),
+        rows,
+    )
+}
+
+pub fn collect(out_dir: &Path, instance: &Instance) -> Result<()> {
+    let mut writer = csv::Writer::from_path(out_dir.join("learn.csv"))?;
+    let (pattern_names, rows) = rows(instance);
+    let metric_names = metric_names(rows.iter().map(|r| &r.metrics));
+    let mut header = vec![
+        "domain".to_owned(),
+        "name".to_owned(),
+        "exit_code".to_owned(),
+        "outcome".to_owned(),
+    ];
+    header.extend(pattern_names);
+    header.extend(metric_names.iter().cloned());
+    writer.write_record(&header)?;
+    for row in rows.iter() {
+        let exit_code = match row.exit_code {
+            Some(code) => code.to_string(),
+            None => "404".to_string(),
+        };
+        let mut record = vec![
+            row.domain.to_owned(),
+            row.learner.to_owned(),
+            exit_code,
+            row.outcome.to_string(),
+        ];
+        record.extend(row.attributes.iter().cloned());
+        record.extend(metric_values(&metric_names, &row.metrics));
+        writer.write_record(&record)?;
     }
+    writer.flush()?;
     Ok(())
 }
@@ -0,0 +1,204 @@
+use anyhow::{bail, Result};
+use log::{info, warn};
+use std::collections::{BTreeSet, HashMap, HashSet};
+use std::fs;
+use std::path::PathBuf;
+
+/// Column naming the results dir a row was merged from
+const SOURCE: &str = "source";
+/// Column marking rows whose duplicates differ from them
+const CONFLICT: &str = "conflict";
+
+/// Result files with their leading columns and the columns identifying a run
+const FILES: [(&str, &[&str], &[&str]); 2] = [
+    (
+        "learn.csv",
+        &["domain", "name", "exit_code", "outcome"],
+        &["name", "domain"],
+    ),
+    (
+        "solve.csv",
+        &["domain", "problem", "name", "exit_code", "outcome"],
+        &["name", "domain", "problem"],
+    ),
+];
+
+/// Merges the learn.csv and solve.csv of results dirs into out_dir, with the
+/// union of their columns and the source of each row
+/// Of duplicates that agree in the conflict columns only the first is kept,
+/// taking on columns only the others have, while duplicates that differ in
+/// any of them are all kept and marked as conflicting
+/// Other files, such as output/*.csv and exports, are not merged
+pub fn merge(sources: &[PathBuf], out_dir: &PathBuf, conflict_columns: &[String]) -> Result<()> {
+    if let Some(source) = sources.iter().find(|s| !s.is_dir()) {
+        bail!("{:?} is not a results dir", source);
+    }
+    let mut files = vec![];
+    for (name, leading, key) in FILES.iter() {
+        files.push((
+            name,
+            merge_file(sources, name, leading, key, conflict_columns)?,
+        ));
+    }
+    fs::create_dir_all(out_dir)?;
+    for (name, merged) in files {
+        if let Some((header, rows)) = merged {
+            let mut writer = csv::Writer::from_path(out_dir.join(name))?;
+            writer.write_record(&header)?;
+            for row in rows.iter() {
+                writer.write_record(header.iter().map(|c| row.get(c).map_or("", |v| v)))?;
+            }
+            writer.flush()?;
+        }
+    }
+    Ok(())
+}
+
+/// Header and rows of a merged result file
+type Merged = (Vec<String>, Vec<HashMap<String, String>>);
+
+/// File merged from sources, None if no source has it
+fn merge_file(
+    sources: &[PathBuf],
+    name: &str,
+    leading: &[&str],
+    key: &[&str],
+    conflict_columns: &[String],
+) -> Result<Option<Merged>> {
+    let mut headers: Vec<Vec<String>> = vec![];
+    let mut rows: Vec<HashMap<String, String>> = vec![];
+    let mut by_key: HashMap<Vec<String>, Vec<usize>> = HashMap::new();
+    let mut conflicts: BTreeSet<Vec<String>> = BTreeSet::new();
+    let mut duplicates = 0;
+    for source in sources.iter() {
+        let path = source.join(name);
+        if !path.exists() {
+            continue;
+        }
+        let mut reader = csv::Reader::from_path(&path)?;
+        let header: Vec<String> = reader.headers()?.iter().map(|h| h.to_owned()).collect();
+        if let Some(column) = key.iter().find(|k| !header.iter().any(|h| h == *k)) {
+            bail!("{:?} has no column {}", path, column);
+        }
+        for record in reader.records() {
+            let record = record?;
+            let mut row: HashMap<String, String> = header
+                .iter()
+                .cloned()
+                .zip(record.iter().map(|v| v.to_owned()))
+                .collect();
+            // Rows merged before keep their original source
+            if row.get(SOURCE).is_none_or(|s| s.is_empty()) {
+                row.insert(SOURCE.to_owned(), source.to_string_lossy().to_string());
+            }
+            let row_key: Vec<String> = key.iter().map(|k| row[*k].to_owned()).collect();
+            let same_key = by_key.entry(row_key.to_owned()).or_default();
+            if let Some(i) = same_key
+                .iter()
+                .find(|i| !differ(&rows[**i], &row, conflict_columns))
+            {
+                for (column, value) in row {
+                    rows[*i].entry(column).or_insert(value);
+                }
+                duplicates += 1;
+                continue;
+            }
+            if !same_key.is_empty() {
+                conflicts.insert(row_key);
+            }
+            same_key.push(rows.len());
+            rows.push(row);
+        }
+        headers.push(header);
+    }
+    if headers.is_empty() {
+        return Ok(None);
+    }
+    if duplicates > 0 {
+        info!("Dropped {} duplicate rows of {}", duplicates, name);
+    }
+    for conflict in conflicts.iter() {
+        warn!(
+            "Conflicting duplicates of {} in {}",
+            conflict.join("/"),
+            name
+        );
+    }
+    let conflicting: HashSet<usize> = conflicts
+        .iter()
+        .flat_map(|k| by_key[k].iter().cloned())
+        .collect();
+
+    let mut header: Vec<String> = leading
+        .iter()
+        .filter(|c| headers.iter().any(|h| h.iter().any(|h| h == *c)))
+        .map(|c| c.to_string())
+        .collect();
+    for column in headers.iter().flatten() {
+        if !header.contains(column) && column != SOURCE && column != CONFLICT {
+            header.push(column.to_owned());
+        }
+    }
+    header.extend([SOURCE.to_owned(), CONFLICT.to_owned()]);
+    let rows = rows
+        .into_iter()
+        .enumerate()
+        .map(|(i, mut row)| {
+            row.insert(CONFLICT.to_owned(), conflicting.contains(&i).to_string());
+            row
+        })
+        .collect();
+    Ok(Some((header, rows)))
+}
+
+/// Whether rows differ in any of columns that both have
+fn differ(a: &HashMap<String, String>, b: &HashMap<String, String>, columns: &[String]) -> bool {
+    columns
+        .iter()
+        .any(|c| a.get(c).is_some_and(|v| b.get(c).is_some_and(|w| w != v)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn write(dir: &PathBuf, content: &str) -> Result<()> {
+        fs::create_dir_all(dir)?;
+        fs::write(dir.join("learn.csv"), content)?;
+        Ok(())
+    }
+
+    fn merged(sources: &[PathBuf], columns: &[&str]) -> Result<Vec<csv::StringRecord>> {
+        let columns: Vec<String> = columns.iter().map(|c| c.to_string()).collect();
+        let out_dir = sources[0].parent().unwrap().join("merged");
+        merge(sources, &out_dir, &columns)?;
+        let mut reader = csv::Reader::from_path(out_dir.join("learn.csv"))?;
+        Ok(reader.records().collect::<csv::Result<_>>()?)
+    }
+
+    #[test]
+    fn duplicates_conflict_only_in_conflict_columns() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let (a, b) = (dir.path().join("a"), dir.path().join("b"));
+        write(
+            &a,
+            "domain,name,exit_code,outcome,run_time,cpus\n\
+             d,l,0,ok,1.5,0\n\
+             d,m,0,ok,2.0,1\n",
+        )?;
+        write(
+            &b,
+            "domain,name,exit_code,outcome,run_time,cpus\n\
+             d,l,0,ok,1.7,3\n\
+             d,m,1,failed,2.0,1\n",
+        )?;
+        let rows = merged(&[a.clone(), b.clone()], &["exit_code", "outcome"])?;
+        let conflicts: Vec<(&str, &str)> = rows.iter().map(|r| (&r[1], &r[7])).collect();
+        assert_eq!(conflicts, [("l", "false"), ("m", "true"), ("m", "true")]);
+
+        let rows = merged(&[a, b], &["cpus"])?;
+        let conflicts: Vec<(&str, &str)> = rows.iter().map(|r| (&r[1], &r[7])).collect();
+        assert_eq!(conflicts, [("l", "true"), ("m", "false"), ("l", "true")]);
+        Ok(())
+    }
+}
@@ -1,22 +1,112 @@
+pub mod compare;
 mod csv;
+pub mod export;
+mod html;
+pub mod latex;
 mod learn;
+pub mod merge;
+pub mod plot;
 mod solve;
+pub mod stats;
+pub mod table;
 
-use crate::setup::instance::Instance;
+pub use learn::LearnRow;
+pub use solve::SolveRow;
+
+use crate::setup::instance::{Instance, INTERRUPTED_FILE, SKIPPED_FILE};
 use crate::setup::suite::Attribute;
 use anyhow::Result;
-use std::collections::HashSet;
+use std::collections::{BTreeMap, BTreeSet, HashSet};
+use std::fmt;
 use std::fs;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 
-pub fn eval(out_dir: &PathBuf, instance: &Instance) -> Result<()> {
+pub fn eval(
+    out_dir: &PathBuf,
+    instance: &Instance,
+    formats: &[export::Format],
+    provenance: bool,
+) -> Result<()> {
     fs::create_dir_all(out_dir)?;
-    let _ = csv::collect(out_dir, instance);
+    let _ = csv::collect(out_dir, instance, provenance);
     let _ = learn::collect(out_dir, instance);
     let _ = solve::collect(out_dir, instance);
+    if !formats.is_empty() {
+        let results = results(instance);
+        for format in formats.iter() {
+            export::write(out_dir, instance, &results, *format)?;
+        }
+    }
     Ok(())
 }
 
+/// How a run ended, as far as can be told from its run dir
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Outcome {
+    /// Has an exit code
+    Finished,
+    /// Was never started as its solver gave up on the task
+    Skipped,
+    /// Was stopped by an interrupt
+    Interrupted,
+    /// Did not finish for any other reason
+    Missing,
+}
+
+impl Outcome {
+    pub fn of(dir: &Path, exit_code: Option<i32>) -> Self {
+        if exit_code.is_some() {
+            Outcome::Finished
+        } else if dir.join(SKIPPED_FILE).exists() {
+            Outcome::Skipped
+        } else if dir.join(INTERRUPTED_FILE).exists() {
+            Outcome::Interrupted
+        } else {
+            Outcome::Missing
+        }
+    }
+}
+
+impl fmt::Display for Outcome {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let outcome = match self {
+            Outcome::Finished => "finished",
+            Outcome::Skipped => "skipped",
+            Outcome::Interrupted => "interrupted",
+            Outcome::Missing => "missing",
+        };
+        write!(f, "{}", outcome)
+    }
+}
+
+/// Exit code recorded in a run dir, None if the run did not finish
+pub fn read_exit_code(dir: &Path) -> Option<i32> {
+    fs::read_to_string(dir.join("exit_code"))
+        .ok()
+        .and_then(|c| c.trim().parse().ok())
+}
+
+/// Typed results of an instance, along with the attribute names of each stage
+#[derive(Debug, Clone)]
+pub struct Results {
+    pub learn_attributes: Vec<String>,
+    pub learn: Vec<LearnRow>,
+    pub solve_attributes: Vec<String>,
+    pub solve: Vec<SolveRow>,
+}
+
+pub fn results(instance: &Instance) -> Results {
+    let (learn_attributes, learn) = learn::rows(instance);
+    let (solve_attributes, solve) = solve::rows(instance);
+    Results {
+        learn_attributes,
+        learn,
+        solve_attributes,
+        solve,
+    }
+}
+
 pub(super) fn pattern_names(attributes: Vec<&Attribute>) -> Vec<&str> {
     let names: HashSet<&str> = attributes
         .iter()
@@ -30,10 +120,10 @@ pub(super) fn pattern_names(attributes: Vec<&Attribute>) -> Vec<&str> {
     names.into_iter().collect()
 }
 
-pub(super) fn pattern_values<'a>(
+pub(super) fn pattern_values(
     pattern_names: &Vec<&str>,
     attribute: &Attribute,
-    content: &'a str,
+    content: &str,
 ) -> Vec<String> {
     pattern_names
         .iter()
@@ -46,3 +136,37 @@ pub(super) fn pattern_values<'a>(
         })
         .collect()
 }
+
+/// Sorted names of all metrics recorded in any of the runs
+pub(super) fn metric_names<'a>(
+    metrics: impl Iterator<Item = &'a BTreeMap<String, String>>,
+) -> Vec<String> {
+    let names: BTreeSet<&String> = metrics.flat_map(|m| m.keys()).collect();
+    names.into_iter().cloned().collect()
+}
+
+pub(super) fn metric_values(
+    metric_names: &[String],
+    metrics: &BTreeMap<String, String>,
+) -> Vec<String> {
+    metric_names
+        .iter()
+        .map(|n| metrics.get(n).cloned().unwrap_or_default())
+        .collect()
+}
+
+/// Escapes characters with special meaning in HTML and SVG
+pub(super) fn escape_xml(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+/// Value with the given number of decimals, or none if it is integral
+pub(super) fn format_number(v: f64, decimals: usize) -> String {
+    match v.fract() == 0.0 {
+        true => format!("{:.0}", v),
+        false => format!("{:.*}", decimals, v),
+    }
+}
@@ -0,0 +1,338 @@
+use super::table::{self, Table};
+use super::{escape_xml, format_number};
+use anyhow::{bail, Result};
+use std::fmt::Write;
+use std::fs;
+use std::path::PathBuf;
+
+const WIDTH: f64 = 640.0;
+const HEIGHT: f64 = 420.0;
+/// Space left of, right of, above and below the plot area
+const MARGIN: (f64, f64, f64, f64) = (70.0, 20.0, 20.0, 50.0);
+const COLORS: [&str; 10] = [
+    "#1f77b4", "#ff7f0e", "#2ca02c", "#d62728", "#9467bd", "#8c564b", "#e377c2", "#7f7f7f",
+    "#bcbd22", "#17becf",
+];
+
+/// Times of the problems a solver solved, in ascending order
+struct Curve<'a> {
+    solver: &'a str,
+    times: Vec<f64>,
+    /// Number of problems the solver ran on
+    total: usize,
+}
+
+/// Writes cactus plots, the time needed for each number of solved problems,
+/// and coverage over time per solver of the results at path into out_dir, as
+/// csv data and svg images. Times are taken from the time column, on a
+/// logarithmic axis if log is given
+pub fn plot(results: &PathBuf, time: &str, log: bool, out_dir: &PathBuf) -> Result<()> {
+    let table = table::read(results)?;
+    table.require(&[time.to_owned()])?;
+    let curves = curves(&table, time, log);
+    if curves.iter().all(|c| c.times.is_empty()) {
+        bail!("No solved problems with a {} to plot", time);
+    }
+    fs::create_dir_all(out_dir)?;
+
+    let mut cactus = csv::Writer::from_path(out_dir.join("cactus.csv"))?;
+    cactus.write_record(["solver", "solved", time])?;
+    let mut coverage = csv::Writer::from_path(out_dir.join("coverage.csv"))?;
+    coverage.write_record(["solver", time, "solved", "coverage"])?;
+    for curve in curves.iter() {
+        for (i, t) in curve.times.iter().enumerate() {
+            let solved = (i + 1).to_string();
+            cactus.write_record([curve.solver, &solved, &t.to_string()])?;
+            coverage.write_record([
+                curve.solver,
+                &t.to_string(),
+                &solved,
+                &((i + 1) as f64 / curve.total as f64).to_string(),
+            ])?;
+        }
+    }
+    cactus.flush()?;
+    coverage.flush()?;
+
+    let times = curves.iter().flat_map(|c| c.times.iter().cloned());
+    let (min, max) = (
+        times.clone().fold(f64::INFINITY, f64::min),
+        times.fold(0.0, f64::max),
+    );
+    // A logarithmic axis spans whole powers of 10
+    let time_axis = Axis {
+        label: time.to_owned(),
+        min: match log {
+            true => 10f64.powf(min.log10().floor()),
+            false => 0.0,
+        },
+        max: match log {
+            true => 10f64.powf(max.log10().ceil()),
+            false => max,
+        },
+        log,
+    };
+    let most_solved = curves.iter().map(|c| c.times.len()).max().unwrap_or(0);
+    // At least 5, such that ticks are whole numbers
+    let solved_axis = Axis {
+        label: "solved problems".to_owned(),
+        min: 0.0,
+        max: most_solved.max(5) as f64,
+        log: false,
+    };
+    let cactus_lines: Vec<Vec<(f64, f64)>> = curves
+        .iter()
+        .map(|c| {
+            c.times
+                .iter()
+                .enumerate()
+                .map(|(i, t)| ((i + 1) as f64, *t))
+                .collect()
+        })
+        .collect();
+    fs::write(
+        out_dir.join("cactus.svg"),
+        svg(&curves, &solved_axis, &time_axis, &cactus_lines)?,
+    )?;
+
+    let coverage_axis = Axis {
+        label: "coverage (%)".to_owned(),
+        min: 0.0,
+        max: 100.0,
+        log: false,
+    };
+    // Steps up at each solved time, continued to the largest time
+    let coverage_lines: Vec<Vec<(f64, f64)>> = curves
+        .iter()
+        .map(|c| {
+            let percent = |solved: usize| 100.0 * solved as f64 / c.total as f64;
+            let mut points = vec![(time_axis.min, 0.0)];
+            for (i, t) in c.times.iter().enumerate() {
+                points.push((*t, percent(i)));
+                points.push((*t, percent(i + 1)));
+            }
+            points.push((time_axis.max, percent(c.times.len())));
+            points
+        })
+        .collect();
+    fs::write(
+        out_dir.join("coverage.svg"),
+        svg(&curves, &time_axis, &coverage_axis, &coverage_lines)?,
+    )?;
+    Ok(())
+}
+
+/// Curves of all solvers, in order of first appearance
+/// Times that are not positive are dropped for a logarithmic axis
+fn curves<'a>(table: &'a Table, time: &str, log: bool) -> Vec<Curve<'a>> {
+    table
+        .solvers()
+        .into_iter()
+        .map(|solver| {
+            let rows: Vec<_> = table.rows.iter().filter(|r| r.solver == solver).collect();
+            let mut times: Vec<f64> = rows
+                .iter()
+                .filter(|r| r.solved())
+                .filter_map(|r| r.number(time))
+                .filter(|t| !log || *t > 0.0)
+                .collect();
+            times.sort_by(|a, b| a.total_cmp(b));
+            Curve {
+                solver,
+                times,
+                total: rows.len(),
+            }
+        })
+        .collect()
+}
+
+struct Axis {
+    label: String,
+    min: f64,
+    max: f64,
+    log: bool,
+}
+
+impl Axis {
+    /// Position of value between 0 and 1
+    fn fraction(&self, value: f64) -> f64 {
+        let (value, min, max) = match self.log {
+            true => (value.log10(), self.min.log10(), self.max.log10()),
+            false => (value, self.min, self.max),
+        };
+        match max > min {
+            true => (value - min) / (max - min),
+            false => 0.5,
+        }
+    }
+
+    /// Values to label, the powers of 10 a logarithmic axis spans and otherwise
+    /// multiples of 1, 2 or 5 times a power of 10
+    fn ticks(&self) -> Vec<f64> {
+        if self.log {
+            let low = self.min.log10().round() as i32;
+            let high = self.max.log10().round() as i32;
+            return (low..=high).map(|e| 10f64.powi(e)).collect();
+        }
+        let Some(step) = self.step() else {
+            return vec![self.min];
+        };
+        let mut ticks = vec![];
+        let mut tick = (self.min / step).ceil() * step;
+        while tick <= self.max + step * 1e-9 {
+            ticks.push(tick);
+            tick += step;
+        }
+        ticks
+    }
+
+    /// Distance between ticks of a linear axis, None if it spans no range
+    fn step(&self) -> Option<f64> {
+        let range = self.max - self.min;
+        if range <= 0.0 {
+            return None;
+        }
+        let magnitude = 10f64.powf((range / 5.0).log10().floor());
+        let step = [1.0, 2.0, 5.0, 10.0]
+            .iter()
+            .map(|m| m * magnitude)
+            .find(|s| range / s <= 6.0)
+            .unwrap_or(10.0 * magnitude);
+        Some(step)
+    }
+
+    /// Decimals needed to label tick, such that ticks are told apart
+    fn decimals(&self, tick: f64) -> usize {
+        let precision = match self.log {
+            true => tick,
+            false => self.step().unwrap_or(1.0),
+        };
+        (-precision.log10().floor()).max(0.0) as usize
+    }
+}
+
+/// Standalone svg of one line per curve, with axes and a legend
+fn svg(curves: &[Curve], x: &Axis, y: &Axis, lines: &[Vec<(f64, f64)>]) -> Result<String> {
+    let (left, right, top, bottom) = MARGIN;
+    let plot_width = WIDTH - left - right;
+    let plot_height = HEIGHT - top - bottom;
+    let px = |v: f64| left + x.fraction(v) * plot_width;
+    let py = |v: f64| top + (1.0 - y.fraction(v)) * plot_height;
+
+    let mut svg = String::new();
+    writeln!(
+        svg,
+        "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{}\" height=\"{}\" \
+         font-family=\"sans-serif\" font-size=\"12\">",
+        WIDTH, HEIGHT
+    )?;
+    writeln!(
+        svg,
+        "<rect width=\"{}\" height=\"{}\" fill=\"white\"/>",
+        WIDTH, HEIGHT
+    )?;
+    for tick in x.ticks() {
+        writeln!(
+            svg,
+            "<line x1=\"{0:.1}\" y1=\"{1:.1}\" x2=\"{0:.1}\" y2=\"{2:.1}\" stroke=\"#ddd\"/>\
+             <text x=\"{0:.1}\" y=\"{3:.1}\" text-anchor=\"middle\">{4}</text>",
+            px(tick),
+            top,
+            top + plot_height,
+            top + plot_height + 16.0,
+            format_number(tick, x.decimals(tick))
+        )?;
+    }
+    for tick in y.ticks() {
+        writeln!(
+            svg,
+            "<line x1=\"{0:.1}\" y1=\"{1:.1}\" x2=\"{2:.1}\" y2=\"{1:.1}\" stroke=\"#ddd\"/>\
+             <text x=\"{3:.1}\" y=\"{4:.1}\" text-anchor=\"end\">{5}</text>",
+            left,
+            py(tick),
+            left + plot_width,
+            left - 6.0,
+            py(tick) + 4.0,
+            format_number(tick, y.decimals(tick))
+        )?;
+    }
+    writeln!(
+        svg,
+        "<rect x=\"{}\" y=\"{}\" width=\"{}\" height=\"{}\" fill=\"none\" stroke=\"black\"/>",
+        left, top, plot_width, plot_height
+    )?;
+    writeln!(
+        svg,
+        "<text x=\"{:.1}\" y=\"{:.1}\" text-anchor=\"middle\">{}</text>",
+        left + plot_width / 2.0,
+        HEIGHT - 10.0,
+        escape_xml(&x.label)
+    )?;
+    writeln!(
+        svg,
+        "<text transform=\"translate(16,{:.1}) rotate(-90)\" text-anchor=\"middle\">{}</text>",
+        top + plot_height / 2.0,
+        escape_xml(&y.label)
+    )?;
+    for (i, (curve, line)) in curves.iter().zip(lines.iter()).enumerate() {
+        let color = COLORS[i % COLORS.len()];
+        let points: Vec<String> = line
+            .iter()
+            .map(|(vx, vy)| format!("{:.1},{:.1}", px(*vx), py(*vy)))
+            .collect();
+        writeln!(
+            svg,
+            "<polyline points=\"{}\" fill=\"none\" stroke=\"{}\" stroke-width=\"2\"/>",
+            points.join(" "),
+            color
+        )?;
+        let legend_y = top + 16.0 + 18.0 * i as f64;
+        writeln!(
+            svg,
+            "<line x1=\"{0:.1}\" y1=\"{1:.1}\" x2=\"{2:.1}\" y2=\"{1:.1}\" stroke=\"{3}\" \
+             stroke-width=\"2\"/><text x=\"{4:.1}\" y=\"{5:.1}\">{6}</text>",
+            left + 10.0,
+            legend_y,
+            left + 30.0,
+            color,
+            left + 36.0,
+            legend_y + 4.0,
+            escape_xml(curve.solver)
+        )?;
+    }
+    writeln!(svg, "</svg>")?;
+    Ok(svg)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn axis(min: f64, max: f64, log: bool) -> Axis {
+        Axis {
+            label: String::new(),
+            min,
+            max,
+            log,
+        }
+    }
+
+    #[test]
+    fn ticks_are_labelled_with_the_decimals_they_need() {
+        let labels = |a: Axis| -> Vec<String> {
+            a.ticks()
+                .iter()
+                .map(|t| format_number(*t, a.decimals(*t)))
+                .collect()
+        };
+        assert_eq!(
+            labels(axis(0.0, 100.0, false)),
+            ["0", "20", "40", "60", "80", "100"]
+        );
+        assert_eq!(
+            labels(axis(0.0, 0.5, false)),
+            ["0", "0.1", "0.2", "0.3", "0.4", "0.5"]
+        );
+        assert_eq!(labels(axis(0.01, 10.0, true)), ["0.01", "0.1", "1", "10"]);
+    }
+}
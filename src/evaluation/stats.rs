@@ -0,0 +1,234 @@
+/// Outcome of a two-sided Wilcoxon signed-rank test
+#[derive(Debug, Clone)]
+pub struct Wilcoxon {
+    /// Number of non-zero differences the test is based on
+    pub n: usize,
+    /// Number of zero differences, which are dropped
+    pub zeros: usize,
+    /// Rank sums of positive and negative differences
+    pub w_plus: f64,
+    pub w_minus: f64,
+    pub p: f64,
+    /// Whether p is exact rather than from the normal approximation
+    pub exact: bool,
+}
+
+/// Largest number of differences for which p is computed exactly
+const EXACT_LIMIT: usize = 50;
+
+/// Tests whether differences are symmetric around zero
+/// Returns None if all differences are zero
+pub fn wilcoxon(differences: &[f64]) -> Option<Wilcoxon> {
+    let mut nonzero: Vec<f64> = differences.iter().cloned().filter(|d| *d != 0.0).collect();
+    let zeros = differences.len() - nonzero.len();
+    let n = nonzero.len();
+    if n == 0 {
+        return None;
+    }
+    nonzero.sort_by(|a, b| a.abs().total_cmp(&b.abs()));
+    // Ranks are doubled, such that averaged ranks of ties stay integral
+    let mut ranks = vec![0; n];
+    let mut ties = vec![];
+    let mut i = 0;
+    while i < n {
+        let mut j = i;
+        while j + 1 < n && nonzero[j + 1].abs() == nonzero[i].abs() {
+            j += 1;
+        }
+        for rank in ranks[i..=j].iter_mut() {
+            *rank = i + j + 2;
+        }
+        ties.push(j - i + 1);
+        i = j + 1;
+    }
+    let plus: usize = (0..n).filter(|i| nonzero[*i] > 0.0).map(|i| ranks[i]).sum();
+    let total: usize = ranks.iter().sum();
+    let w_plus = plus as f64 / 2.0;
+    let w_minus = (total - plus) as f64 / 2.0;
+    let (p, exact) = match n <= EXACT_LIMIT {
+        true => (exact_p(&ranks, plus), true),
+        false => (normal_p(n, &ties, w_plus), false),
+    };
+    Some(Wilcoxon {
+        n,
+        zeros,
+        w_plus,
+        w_minus,
+        p,
+        exact,
+    })
+}
+
+/// Two-sided p of a doubled rank sum, by counting the subsets of ranks
+fn exact_p(ranks: &[usize], plus: usize) -> f64 {
+    let total: usize = ranks.iter().sum();
+    let mut counts = vec![0.0; total + 1];
+    counts[0] = 1.0;
+    for rank in ranks.iter() {
+        for sum in (*rank..=total).rev() {
+            counts[sum] += counts[sum - rank];
+        }
+    }
+    let all = 2f64.powi(ranks.len() as i32);
+    let lower: f64 = counts[..=plus].iter().sum::<f64>() / all;
+    let upper: f64 = counts[plus..].iter().sum::<f64>() / all;
+    (2.0 * lower.min(upper)).min(1.0)
+}
+
+/// Two-sided p from the normal approximation, with tie and continuity
+/// correction
+fn normal_p(n: usize, ties: &[usize], w_plus: f64) -> f64 {
+    let n = n as f64;
+    let mean = n * (n + 1.0) / 4.0;
+    let tie_correction: f64 = ties
+        .iter()
+        .map(|t| (*t as f64).powi(3) - *t as f64)
+        .sum::<f64>()
+        / 48.0;
+    let sd = (n * (n + 1.0) * (2.0 * n + 1.0) / 24.0 - tie_correction).sqrt();
+    let diff = w_plus - mean;
+    let z = (diff.abs() - 0.5).max(0.0) / sd;
+    erfc(z / 2f64.sqrt()).min(1.0)
+}
+
+/// Complementary error function, with a fractional error below 1.2e-7
+fn erfc(x: f64) -> f64 {
+    let z = x.abs();
+    let t = 1.0 / (1.0 + 0.5 * z);
+    let r = t
+        * (-z * z - 1.26551223
+            + t * (1.00002368
+                + t * (0.37409196
+                    + t * (0.09678418
+                        + t * (-0.18628806
+                            + t * (0.27886807
+                                + t * (-1.13520398
+                                    + t * (1.48851587 + t * (-0.82215223 + t * 0.17087277)))))))))
+            .exp();
+    match x >= 0.0 {
+        true => r,
+        false => 2.0 - r,
+    }
+}
+
+/// How the values of an attribute are summarized
+#[derive(Debug, Copy, Clone, PartialEq, Eq, clap::ValueEnum)]
+pub enum Aggregation {
+    Sum,
+    Mean,
+    Median,
+    Min,
+    Max,
+    /// Geometric mean, ignoring values that are not positive
+    Geomean,
+}
+
+impl Aggregation {
+    /// Summary of values, None if there are none
+    pub fn apply(&self, values: &[f64]) -> Option<f64> {
+        if values.is_empty() {
+            return None;
+        }
+        let n = values.len() as f64;
+        match self {
+            Aggregation::Sum => Some(values.iter().sum()),
+            Aggregation::Mean => Some(values.iter().sum::<f64>() / n),
+            Aggregation::Median => {
+                let mut sorted = values.to_vec();
+                sorted.sort_by(|a, b| a.total_cmp(b));
+                let mid = sorted.len() / 2;
+                match sorted.len() % 2 {
+                    0 => Some((sorted[mid - 1] + sorted[mid]) / 2.0),
+                    _ => Some(sorted[mid]),
+                }
+            }
+            Aggregation::Min => values.iter().cloned().reduce(f64::min),
+            Aggregation::Max => values.iter().cloned().reduce(f64::max),
+            Aggregation::Geomean => {
+                let logs: Vec<f64> = values
+                    .iter()
+                    .filter(|v| **v > 0.0)
+                    .map(|v| v.ln())
+                    .collect();
+                match logs.is_empty() {
+                    true => None,
+                    false => Some((logs.iter().sum::<f64>() / logs.len() as f64).exp()),
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_close(a: f64, b: f64) {
+        assert!((a - b).abs() < 1e-6, "{} != {}", a, b);
+    }
+
+    #[test]
+    fn exact_p_of_smallest_samples() {
+        // All 5 differences positive is the most extreme of 2^5 outcomes
+        let w = wilcoxon(&[1.0, 2.0, 3.0, 4.0, 5.0]).unwrap();
+        assert_eq!((w.n, w.zeros, w.w_plus, w.w_minus), (5, 0, 15.0, 0.0));
+        assert!(w.exact);
+        assert_close(w.p, 0.0625);
+        // Rank sums of 0 and 1 are 2 of 2^6 outcomes
+        let w = wilcoxon(&[-1.0, 2.0, 3.0, 4.0, 5.0, 6.0]).unwrap();
+        assert_eq!((w.w_plus, w.w_minus), (20.0, 1.0));
+        assert_close(w.p, 0.0625);
+    }
+
+    #[test]
+    fn drops_zeros_and_averages_tied_ranks() {
+        let w = wilcoxon(&[0.0, 0.0, 1.0, 2.0, 3.0, 4.0, 5.0]).unwrap();
+        assert_eq!((w.n, w.zeros), (5, 2));
+        assert_close(w.p, 0.0625);
+        let w = wilcoxon(&[1.0, -1.0, 2.0, -2.0]).unwrap();
+        assert_eq!((w.w_plus, w.w_minus), (5.0, 5.0));
+        assert_close(w.p, 1.0);
+        assert!(wilcoxon(&[0.0, 0.0]).is_none());
+    }
+
+    #[test]
+    fn normal_approximation_of_large_samples() {
+        let differences: Vec<f64> = (1..=60).map(|d| d as f64).collect();
+        let w = wilcoxon(&differences).unwrap();
+        assert!(!w.exact);
+        assert!(w.p < 1e-10);
+        let differences: Vec<f64> = (1..=60)
+            .map(|d| match d % 2 {
+                0 => d as f64,
+                _ => -d as f64,
+            })
+            .collect();
+        let w = wilcoxon(&differences).unwrap();
+        assert!(w.p > 0.5);
+    }
+
+    #[test]
+    fn erfc_is_accurate() {
+        assert_close(erfc(0.0), 1.0);
+        assert_close(erfc(1.0), 0.157_299_207);
+        assert_close(erfc(-1.0), 1.842_700_793);
+        assert_close(erfc(3.0), 2.209_050e-5);
+    }
+
+    #[test]
+    fn aggregates() {
+        let values = [4.0, 1.0, 2.0, 8.0];
+        assert_eq!(Aggregation::Sum.apply(&values), Some(15.0));
+        assert_eq!(Aggregation::Mean.apply(&values), Some(3.75));
+        assert_eq!(Aggregation::Median.apply(&values), Some(3.0));
+        assert_eq!(Aggregation::Median.apply(&values[..3]), Some(2.0));
+        assert_eq!(Aggregation::Min.apply(&values), Some(1.0));
+        assert_eq!(Aggregation::Max.apply(&values), Some(8.0));
+        assert_close(
+            Aggregation::Geomean.apply(&values).unwrap(),
+            64f64.powf(0.25),
+        );
+        assert_eq!(Aggregation::Geomean.apply(&[0.0, -1.0]), None);
+        assert_eq!(Aggregation::Sum.apply(&[]), None);
+    }
+}
@@ -0,0 +1,109 @@
+use anyhow::{bail, Result};
+use std::collections::HashMap;
+use std::path::PathBuf;
+
+/// Columns of solve.csv identifying a run and how it ended
+const FIXED_COLUMNS: [&str; 5] = ["domain", "problem", "name", "exit_code", "outcome"];
+
+/// Solver run as read back from a solve.csv
+#[derive(Debug, Clone)]
+pub struct Row {
+    pub domain: String,
+    pub problem: String,
+    pub solver: String,
+    pub exit_code: Option<i32>,
+    /// Attribute and metric values by column name
+    pub values: HashMap<String, String>,
+}
+
+impl Row {
+    pub fn solved(&self) -> bool {
+        self.exit_code == Some(0)
+    }
+
+    /// Value of column as a number, None if missing or not numeric
+    pub fn number(&self, column: &str) -> Option<f64> {
+        self.values.get(column).and_then(|v| v.parse().ok())
+    }
+}
+
+/// Contents of a solve.csv
+#[derive(Debug, Clone)]
+pub struct Table {
+    /// Attribute and metric columns, in file order
+    pub columns: Vec<String>,
+    pub rows: Vec<Row>,
+}
+
+impl Table {
+    /// Solvers in order of first appearance
+    pub fn solvers(&self) -> Vec<&str> {
+        let mut solvers: Vec<&str> = vec![];
+        for row in self.rows.iter() {
+            if !solvers.contains(&row.solver.as_str()) {
+                solvers.push(&row.solver);
+            }
+        }
+        solvers
+    }
+
+    /// Fails if any of columns is missing
+    pub fn require(&self, columns: &[String]) -> Result<()> {
+        for column in columns.iter() {
+            if !self.columns.contains(column) {
+                bail!(
+                    "Results have no column {}, available are: {}",
+                    column,
+                    self.columns.join(", ")
+                );
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Reads the solve.csv of a results dir, or the given csv file
+pub fn read(results: &PathBuf) -> Result<Table> {
+    let path = match results.is_dir() {
+        true => results.join("solve.csv"),
+        false => results.to_owned(),
+    };
+    let mut reader = csv::Reader::from_path(&path)?;
+    let header: Vec<String> = reader.headers()?.iter().map(|h| h.to_owned()).collect();
+    let position = |name: &str| header.iter().position(|h| h == name);
+    let (Some(domain), Some(problem), Some(solver), Some(exit_code)) = (
+        position("domain"),
+        position("problem"),
+        position("name"),
+        position("exit_code"),
+    ) else {
+        bail!("{:?} is not a solve.csv", path);
+    };
+    let columns: Vec<String> = header
+        .iter()
+        .filter(|h| !FIXED_COLUMNS.contains(&h.as_str()))
+        .cloned()
+        .collect();
+    let mut rows = vec![];
+    for record in reader.records() {
+        let record = record?;
+        let field = |i: usize| record.get(i).unwrap_or_default().to_owned();
+        rows.push(Row {
+            domain: field(domain),
+            problem: field(problem),
+            solver: field(solver),
+            // Unfinished runs are written with exit code 404
+            exit_code: match field(exit_code).parse() {
+                Ok(404) | Err(_) => None,
+                Ok(code) => Some(code),
+            },
+            values: header
+                .iter()
+                .enumerate()
+                .filter(|(_, h)| !FIXED_COLUMNS.contains(&h.as_str()))
+                .map(|(i, h)| (h.to_owned(), field(i)))
+                .collect(),
+        });
+    }
+    Ok(Table { columns, rows })
+}
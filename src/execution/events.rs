@@ -0,0 +1,73 @@
+use super::run_name;
+use crate::evaluation::{read_exit_code, Outcome};
+use crate::misc::events::{self, Event};
+use crate::misc::metrics;
+use crate::setup::instance::{Instance, RunKind};
+use std::collections::BTreeMap;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+/// Stages of an execution that have started, such that each is announced once
+#[derive(Debug, Default)]
+pub(super) struct Stages {
+    learn: AtomicBool,
+    solve: AtomicBool,
+}
+
+pub(super) fn queued(instance: &Instance, i: usize) {
+    if events::enabled() {
+        events::emit(&Event::RunQueued {
+            run: i,
+            name: &run_name(instance, i),
+        });
+    }
+}
+
+/// Emits the start of run, preceded by that of its stage if it is the first
+pub(super) fn started(instance: &Instance, stages: &Stages, i: usize) {
+    if !events::enabled() {
+        return;
+    }
+    let (stage, started) = match instance.runs[i].kind {
+        RunKind::Learner => ("learn", &stages.learn),
+        RunKind::Solver { .. } => ("solve", &stages.solve),
+    };
+    if !started.swap(true, Ordering::SeqCst) {
+        events::emit(&Event::StageStarted { stage });
+    }
+    events::emit(&Event::RunStarted {
+        run: i,
+        name: &run_name(instance, i),
+    });
+}
+
+pub(super) fn finished(instance: &Instance, i: usize) {
+    if !events::enabled() {
+        return;
+    }
+    let dir = &instance.runs[i].dir;
+    let exit_code = read_exit_code(dir);
+    events::emit(&Event::RunFinished {
+        run: i,
+        name: &run_name(instance, i),
+        outcome: Outcome::of(dir, exit_code).to_string(),
+        exit_code,
+        metrics: &metrics::read(dir),
+    });
+}
+
+pub(super) fn experiment_finished(instance: &Instance, interrupted: bool) {
+    if !events::enabled() {
+        return;
+    }
+    let mut outcomes: BTreeMap<String, usize> = BTreeMap::new();
+    for run in instance.runs.iter() {
+        let exit_code = read_exit_code(&run.dir);
+        *outcomes
+            .entry(Outcome::of(&run.dir, exit_code).to_string())
+            .or_default() += 1;
+    }
+    events::emit(&Event::ExperimentFinished {
+        interrupted,
+        outcomes: &outcomes,
+    });
+}
@@ -0,0 +1,158 @@
+use crate::misc::metrics;
+use crate::setup::instance::{Instance, MANIFEST_FILE};
+use anyhow::{bail, Result};
+use log::info;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+/// Identifies a run across instances by its runner, task and, for solvers,
+/// problem
+type Key = (String, String, Option<String>);
+
+/// Mean run times in seconds of the runs found in earlier work dirs or
+/// results dirs
+pub fn read(paths: &[PathBuf]) -> Result<HashMap<Key, f64>> {
+    let mut times: HashMap<Key, Vec<f64>> = HashMap::new();
+    for path in paths.iter() {
+        if path.join(MANIFEST_FILE).exists() {
+            read_work_dir(path, &mut times)?;
+        } else if path.join("learn.csv").exists() || path.join("solve.csv").exists() {
+            read_results(path, &mut times)?;
+        } else {
+            bail!("{:?} is neither a work dir nor a results dir", path);
+        }
+    }
+    Ok(times
+        .into_iter()
+        .map(|(key, t)| (key, t.iter().sum::<f64>() / t.len() as f64))
+        .collect())
+}
+
+fn key(runner: &str, task: &str, problem: &str) -> Key {
+    (
+        runner.to_owned(),
+        task.to_owned(),
+        match problem.is_empty() {
+            true => None,
+            false => Some(problem.to_owned()),
+        },
+    )
+}
+
+fn read_work_dir(dir: &Path, times: &mut HashMap<Key, Vec<f64>>) -> Result<()> {
+    let mut reader = csv::Reader::from_path(dir.join(MANIFEST_FILE))?;
+    for record in reader.records() {
+        let record = record?;
+        let (Some(run_dir), Some(runner), Some(task), Some(problem)) =
+            (record.get(0), record.get(1), record.get(2), record.get(3))
+        else {
+            continue;
+        };
+        let time = metrics::read(&dir.join(run_dir))
+            .get(metrics::RUN_TIME)
+            .and_then(|t| t.parse().ok());
+        if let Some(time) = time {
+            times
+                .entry(key(runner, task, problem))
+                .or_default()
+                .push(time);
+        }
+    }
+    Ok(())
+}
+
+fn read_results(dir: &Path, times: &mut HashMap<Key, Vec<f64>>) -> Result<()> {
+    for file in ["learn.csv", "solve.csv"] {
+        let path = dir.join(file);
+        if !path.exists() {
+            continue;
+        }
+        let mut reader = csv::Reader::from_path(path)?;
+        let header = reader.headers()?.clone();
+        let column = |name: &str| header.iter().position(|h| h == name);
+        let (Some(task), Some(runner), Some(run_time)) =
+            (column("domain"), column("name"), column(metrics::RUN_TIME))
+        else {
+            continue;
+        };
+        let problem = column("problem");
+        for record in reader.records() {
+            let record = record?;
+            if let Some(Ok(time)) = record.get(run_time).map(|t| t.parse()) {
+                times
+                    .entry(key(
+                        record.get(runner).unwrap_or_default(),
+                        record.get(task).unwrap_or_default(),
+                        problem.and_then(|p| record.get(p)).unwrap_or_default(),
+                    ))
+                    .or_default()
+                    .push(time);
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Expected run time in seconds of each run of instance, falling back to its
+/// time limit for runs not found in history, or infinity without one
+pub fn estimates(instance: &Instance, history: &HashMap<Key, f64>) -> Vec<f64> {
+    let mut known = 0;
+    let estimates = (0..instance.runs.len())
+        .map(|i| {
+            let (runner, task, problem) = instance.run_key(i);
+            let key = (
+                runner.to_owned(),
+                task.to_owned(),
+                problem.map(|p| p.to_owned()),
+            );
+            match history.get(&key) {
+                Some(time) => {
+                    known += 1;
+                    *time
+                }
+                None => instance
+                    .time_limit(i)
+                    .map(|t| t as f64)
+                    .unwrap_or(f64::INFINITY),
+            }
+        })
+        .collect();
+    if !history.is_empty() {
+        info!(
+            "Run times of {}/{} runs known from history",
+            known,
+            instance.runs.len()
+        );
+    }
+    estimates
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::setup::{instance, suite};
+    use std::fs;
+
+    #[test]
+    fn reads_run_times_of_work_dirs() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        for file in ["domain.pddl", "p1.pddl"] {
+            fs::write(dir.path().join(file), "")?;
+        }
+        let content = "[[runners]]\nname = \"s\"\npath = \"s.sh\"\nkind = \"Solve\"\n\
+                       [[tasks]]\nname = \"bw, easy\"\ndomain = \"domain.pddl\"\n\
+                       solve = [\"p1.pddl\"]\n";
+        let suite = suite::parse(content, dir.path())?;
+        let work_dir = dir.path().join("work");
+        let instance = instance::generate(&work_dir, suite, &Default::default())?;
+        metrics::record(&instance.runs[0].dir, metrics::RUN_TIME, "1.5")?;
+        let times = read(&[work_dir])?;
+        assert_eq!(
+            times.get(&key("s", "bw, easy", "p1")),
+            Some(&1.5),
+            "{:?}",
+            times
+        );
+        Ok(())
+    }
+}
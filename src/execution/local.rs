@@ -1,138 +1,222 @@
-use crate::misc::logging::ProgressBar;
-use crate::setup::instance::{Instance, Run, RunKind};
-use anyhow::Result;
-use log::{info, trace};
+use super::events;
+use super::history;
+use super::pinning;
+use super::scheduler::Scheduler;
+use super::{Executor, Options, Progress};
+use crate::misc::metrics;
+use crate::setup::instance::{Instance, INTERRUPTED_FILE};
+use anyhow::{bail, Context, Result};
+use log::{info, trace, warn};
 use pretty_duration::pretty_duration;
+use std::collections::HashMap;
+use std::fs;
 use std::path::PathBuf;
-use std::process::Command;
-use std::sync::{mpsc, Arc, Mutex};
-use std::thread::{self, sleep};
+use std::process::{Command, Stdio};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError};
+use std::sync::{Arc, Mutex};
+use std::thread;
 use std::time::{Duration, Instant};
 
-#[derive(Clone, PartialEq, Eq)]
-enum State {
-    Unprocessed,
-    Processing,
-    Processed,
+/// Longest time poll waits for a run to finish
+const POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+/// Executes runs on the local machine with a fixed number of worker threads
+pub struct LocalExecutor {
+    threads: usize,
+    max_memory: Option<usize>,
+    /// Earlier work dirs or results dirs used to order runs
+    history: Vec<PathBuf>,
+    /// Cpus each worker pins its runs to
+    cpu_sets: Option<Vec<Vec<usize>>>,
+    scheduler: Option<Arc<Scheduler>>,
+    /// Process ids of the runners currently executing, by run index, which
+    /// are also the ids of their sessions
+    children: Arc<Mutex<HashMap<usize, u32>>>,
+    /// Signals progress of workers, along with errors running a runner
+    rx: Option<Receiver<Result<()>>>,
+}
+
+impl LocalExecutor {
+    /// Fails if no run could be executed with the options
+    pub fn new(options: &Options) -> Result<Self> {
+        if options.threads == 0 {
+            bail!("At least one thread is needed");
+        }
+        let cpu_sets = match &options.cpus {
+            Some(cpus) => {
+                let cpus = match options.skip_siblings {
+                    true => pinning::without_siblings(cpus),
+                    false => cpus.to_owned(),
+                };
+                Some(pinning::cpu_sets(&cpus, options.cpus_per_run)?)
+            }
+            None => None,
+        };
+        let threads = match &cpu_sets {
+            Some(sets) if sets.len() < options.threads => {
+                warn!(
+                    "Only {} cpu set(s) of {} cpu(s) available, limiting threads accordingly",
+                    sets.len(),
+                    options.cpus_per_run
+                );
+                sets.len()
+            }
+            _ => options.threads,
+        };
+        Ok(Self {
+            threads,
+            max_memory: options.max_memory,
+            history: options.history.to_owned(),
+            cpu_sets,
+            scheduler: None,
+            children: Default::default(),
+            rx: None,
+        })
+    }
 }
 
-pub fn execute(instance: Instance, threads: usize) -> Result<()> {
-    let runs = Arc::new(Mutex::new(
-        instance
-            .runs
-            .iter()
-            .cloned()
-            .zip(
-                instance
-                    .runs
-                    .iter()
-                    .map(|run| match run.skip {
-                        true => State::Processed,
-                        false => State::Unprocessed,
-                    })
-                    .collect::<Vec<_>>(),
-            )
-            .collect::<Vec<(Run, State)>>(),
-    ));
-    let pb = ProgressBar::new(runs.lock().unwrap().len());
-    let (tx, rx) = mpsc::channel();
-    for n in 0..threads {
-        let tx = tx.clone();
-        let runs = runs.clone();
-        thread::spawn(move || loop {
-            let run = {
-                let mut runs = runs.lock().unwrap();
-                let run = runs
-                    .iter()
-                    .enumerate()
-                    .filter_map(|(i, (run, state))| match state {
-                        State::Unprocessed => Some((i, run)),
-                        _ => None,
-                    })
-                    .find(|(_, run)| match run.kind {
-                        RunKind::Learner => true,
-                        RunKind::Solver {
-                            problem_index: _,
-                            depends,
-                        } => {
-                            if let Some(depends) = depends {
-                                runs[depends].1 == State::Processed
-                            } else {
-                                true
-                            }
-                        }
-                    })
-                    .map(|(i, run)| (i.to_owned(), run.to_owned()));
-                if let Some((i, _)) = run {
-                    runs[i].1 = State::Processing;
+impl Executor for LocalExecutor {
+    fn submit(&mut self, instance: &Instance) -> Result<()> {
+        let memory: Vec<usize> = (0..instance.runs.len())
+            .map(|i| instance.memory_limit(i).unwrap_or(0))
+            .collect();
+        let estimates = history::estimates(instance, &history::read(&self.history)?);
+        let scheduler = Arc::new(Scheduler::new(
+            instance,
+            &estimates,
+            memory,
+            self.max_memory,
+        ));
+        let (_, done) = scheduler.progress();
+        for i in (0..instance.runs.len()).filter(|i| !done.contains(i)) {
+            events::queued(instance, i);
+        }
+        let instance = Arc::new(instance.clone());
+        let stages = Arc::new(events::Stages::default());
+        let (tx, rx) = mpsc::channel();
+        for n in 0..self.threads {
+            let cpus = self.cpu_sets.as_ref().map(|sets| sets[n].to_owned());
+            let tx = tx.clone();
+            let instance = instance.clone();
+            let scheduler = scheduler.clone();
+            let stages = stages.clone();
+            let children = self.children.clone();
+            thread::spawn(move || {
+                while let Some(i) = scheduler.next() {
+                    let run = &instance.runs[i];
+                    events::started(&instance, &stages, i);
+                    let _ = tx.send(Ok(()));
+                    // The run is finished even if its runner could not be
+                    // started, such that nothing waits on it
+                    let result = _execute(i, &run.dir, &run.exe, cpus.as_deref(), &children)
+                        .with_context(|| format!("Failed to run {:?}", run.dir));
+                    if scheduler.is_cancelled() && !run.dir.join("exit_code").exists() {
+                        let _ = fs::write(run.dir.join(INTERRUPTED_FILE), "");
+                    }
+                    let skipped = scheduler.finish(i);
+                    events::finished(&instance, i);
+                    for r in skipped {
+                        events::finished(&instance, r);
+                    }
+                    let _ = tx.send(result);
                 }
-                run
-            };
-            if let Some((i, run)) = run {
-                let _ = tx.send((n, Some(i)));
-                let _ = _execute(&run.dir, &run.exe);
-                runs.lock().unwrap()[i].1 = State::Processed;
-            } else {
-                let _ = tx.send((n, None));
-                if runs
-                    .lock()
-                    .unwrap()
-                    .iter()
-                    .all(|(_, state)| state != &State::Unprocessed)
-                {
-                    break;
-                } else {
-                    sleep(Duration::from_millis(50));
+            });
+        }
+        self.scheduler = Some(scheduler);
+        self.rx = Some(rx);
+        Ok(())
+    }
+
+    fn poll(&mut self, _instance: &Instance) -> Result<Progress> {
+        let rx = self.rx.as_ref().expect("poll called before submit");
+        // All workers have exited once the channel is disconnected
+        let mut exited = false;
+        match rx.recv_timeout(POLL_INTERVAL) {
+            Ok(result) => {
+                result?;
+                while let Ok(result) = rx.try_recv() {
+                    result?;
                 }
             }
-        });
+            Err(RecvTimeoutError::Timeout) => {}
+            Err(RecvTimeoutError::Disconnected) => exited = true,
+        }
+        let scheduler = self.scheduler.as_ref().expect("poll called before submit");
+        let (running, finished) = scheduler.progress();
+        Ok(Progress {
+            running,
+            finished,
+            done: exited,
+        })
     }
-    drop(tx);
-    while let Ok((_, i)) = rx.recv() {
-        if let Some(_) = i {
-            pb.inc();
+
+    fn cancel(&mut self, _instance: &Instance) -> Result<()> {
+        if let Some(scheduler) = &self.scheduler {
+            scheduler.cancel();
         }
-        let runs = runs.lock().unwrap();
-        let msg: String = runs
-            .iter()
-            .filter_map(|(run, state)| match state {
-                State::Processing => Some(match run.kind {
-                    RunKind::Learner => format!(
-                        "{}.{}",
-                        instance.runners[run.runner_index].name,
-                        instance.tasks[run.task_index].name
-                    ),
-                    RunKind::Solver {
-                        problem_index,
-                        depends: _,
-                    } => format!(
-                        "{}.{}.{}",
-                        instance.runners[run.runner_index].name,
-                        instance.tasks[run.task_index].name,
-                        instance.tasks[run.task_index].solve[problem_index]
-                    ),
-                }),
-                _ => None,
-            })
-            .collect::<Vec<String>>()
-            .join(", ");
-        pb.msg(msg);
+        for pid in self.children.lock().unwrap().values() {
+            // Runners lead their own session, which also holds the process
+            // groups timeout moves itself and the planner into
+            let _ = Command::new("pkill")
+                .args(["-TERM", "-s", &pid.to_string()])
+                .status();
+        }
+        Ok(())
     }
-    Ok(())
 }
 
-fn _execute(dir: &PathBuf, exe: &PathBuf) -> Result<()> {
-    let dir_name = dir.file_stem().expect("Could not retrieve name of dir");
-    let mut command = Command::new(exe);
+fn _execute(
+    i: usize,
+    dir: &PathBuf,
+    exe: &PathBuf,
+    cpus: Option<&[usize]>,
+    children: &Mutex<HashMap<usize, u32>>,
+) -> Result<()> {
+    // setsid replaces itself with the runner, whose process id thereby is
+    // that of its session
+    let mut command = Command::new("setsid");
+    if let Some(cpus) = cpus {
+        let cpus = pinning::format_cpu_list(cpus);
+        metrics::record(dir, "cpus", &cpus)?;
+        command.args(["taskset", "-c", &cpus]);
+    }
+    command.arg(exe);
     command.current_dir(dir);
+    command.stdout(Stdio::null()).stderr(Stdio::null());
     trace!("Running command: {:?}", command);
     let t = Instant::now();
-    let _ = command.output().expect("Failed to run command");
+    let mut child = command.spawn()?;
+    children.lock().unwrap().insert(i, child.id());
+    let status = child.wait();
+    children.lock().unwrap().remove(&i);
+    status?;
     let elapsed = t.elapsed();
     info!(
         "{} - {}",
-        dir_name.to_str().expect("Could not convert name to string"),
+        dir.file_name().unwrap_or_default().to_string_lossy(),
         pretty_duration(&elapsed, None),
     );
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fails_without_a_worker() {
+        let pinned = Options {
+            threads: 2,
+            cpus: Some(vec![0, 1]),
+            cpus_per_run: 4,
+            ..Default::default()
+        };
+        assert!(LocalExecutor::new(&pinned).is_err());
+        assert!(LocalExecutor::new(&Options::default()).is_err());
+        let pinned = Options {
+            cpus_per_run: 2,
+            ..pinned
+        };
+        assert_eq!(LocalExecutor::new(&pinned).map(|e| e.threads).ok(), Some(1));
+    }
+}
@@ -1,9 +1,21 @@
+mod events;
+mod history;
 mod local;
-mod slurm;
+mod monitor;
+pub mod pinning;
+mod scheduler;
+pub mod slurm;
 
-use crate::setup::instance::Instance;
-use anyhow::Result;
+use crate::setup::instance::{Instance, RunKind};
+use anyhow::{bail, Result};
 use clap::ValueEnum;
+use log::{info, warn};
+use std::fmt;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+pub use local::LocalExecutor;
+pub use slurm::SlurmExecutor;
 
 #[derive(Debug, Copy, Clone, PartialEq, Default, ValueEnum)]
 pub enum ExecutionKind {
@@ -12,9 +24,158 @@ pub enum ExecutionKind {
     Slurm,
 }
 
-pub fn execute(instance: Instance, kind: ExecutionKind, threads: usize) -> Result<()> {
-    match kind {
-        ExecutionKind::Local => local::execute(instance, threads),
-        ExecutionKind::Slurm => slurm::execute(instance),
+static INTERRUPTED: AtomicBool = AtomicBool::new(false);
+
+/// Requests ongoing execution to stop, such as on SIGINT
+pub fn interrupt() {
+    INTERRUPTED.store(true, Ordering::SeqCst);
+}
+
+pub fn interrupted() -> bool {
+    INTERRUPTED.load(Ordering::SeqCst)
+}
+
+/// Error given when execution is stopped by an interrupt
+#[derive(Debug)]
+pub struct Interrupted;
+
+impl fmt::Display for Interrupted {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Execution was interrupted")
+    }
+}
+
+impl std::error::Error for Interrupted {}
+
+/// State of the runs of an instance, as seen by an executor
+#[derive(Debug, Clone, Default)]
+pub struct Progress {
+    /// Indices of runs currently being executed
+    pub running: Vec<usize>,
+    /// Indices of runs that are done, including skipped ones
+    pub finished: Vec<usize>,
+    /// Whether all submitted runs are done
+    pub done: bool,
+}
+
+/// A backend which executes the runs of an instance
+///
+/// Runs marked as skip must not be executed, and a solver run must not be
+/// started before the learner it depends on is done
+pub trait Executor {
+    /// Starts executing the instance without waiting for it to finish
+    fn submit(&mut self, instance: &Instance) -> Result<()>;
+    /// Blocks for at most a backend specific interval, then reports progress
+    /// An error fails the execution and cancels its runs, so progress that
+    /// cannot be determined for now is to be reported as not done instead
+    fn poll(&mut self, instance: &Instance) -> Result<Progress>;
+    /// Stops all submitted runs that are not done
+    fn cancel(&mut self, instance: &Instance) -> Result<()>;
+    /// Whether runs outlive labyr, such that detach can be used
+    fn detachable(&self) -> bool {
+        false
+    }
+    /// Leaves submitted runs to finish on their own, recording what is needed
+    /// to check on them later in the work dir
+    fn detach(&mut self, _instance: &Instance) -> Result<()> {
+        bail!("Detaching is not supported by this executor")
+    }
+}
+
+/// Settings of an execution, some of which only apply to certain executors
+#[derive(Debug, Clone, Default)]
+pub struct Options {
+    /// Number of runs executed at once by the local executor
+    pub threads: usize,
+    /// Memory in MB the local executor may hand out to runs at once, based
+    /// on their memory limits
+    pub max_memory: Option<usize>,
+    /// Cpus the local executor pins runs to, no pinning if None
+    pub cpus: Option<Vec<usize>>,
+    /// Number of cpus each pinned run is given
+    pub cpus_per_run: usize,
+    /// Whether to leave hyperthread siblings of pinned cpus idle
+    pub skip_siblings: bool,
+    /// Whether to leave submitted runs to finish on their own
+    pub detach: bool,
+    /// Earlier work dirs or results dirs whose run times guide the order in
+    /// which the local executor starts runs
+    pub history: Vec<PathBuf>,
+    /// Whether to show progress per runner rather than per stage
+    pub progress_per_runner: bool,
+}
+
+impl ExecutionKind {
+    pub fn executor(&self, options: &Options) -> Result<Box<dyn Executor>> {
+        Ok(match self {
+            ExecutionKind::Local => Box::new(LocalExecutor::new(options)?),
+            ExecutionKind::Slurm => {
+                if !options.history.is_empty() {
+                    warn!("History is only used by the local executor");
+                }
+                Box::new(SlurmExecutor::default())
+            }
+        })
+    }
+}
+
+pub fn execute(instance: Instance, kind: ExecutionKind, options: &Options) -> Result<()> {
+    run(&instance, kind.executor(options)?.as_mut(), options)
+}
+
+/// Executes instance with executor, reporting progress until it is done
+pub fn run(instance: &Instance, executor: &mut dyn Executor, options: &Options) -> Result<()> {
+    if options.detach && !executor.detachable() {
+        bail!("Detaching is not supported by this executor");
+    }
+    executor.submit(instance)?;
+    if options.detach {
+        return executor.detach(instance);
+    }
+    let mut monitor = monitor::Monitor::new(instance, options.progress_per_runner);
+    loop {
+        let progress = match executor.poll(instance) {
+            Ok(progress) => progress,
+            Err(err) => {
+                info!("Cancelling runs");
+                let _ = executor.cancel(instance);
+                return Err(err);
+            }
+        };
+        monitor.update(instance, &progress);
+        if progress.done {
+            break;
+        }
+        if interrupted() {
+            info!("Interrupted, stopping runs");
+            executor.cancel(instance)?;
+            while !executor.poll(instance)?.done {}
+            events::experiment_finished(instance, true);
+            // Handled, such that a later execution is not stopped right away
+            INTERRUPTED.store(false, Ordering::SeqCst);
+            return Err(Interrupted.into());
+        }
+    }
+    events::experiment_finished(instance, false);
+    Ok(())
+}
+
+/// Human readable name of run
+pub fn run_name(instance: &Instance, run_index: usize) -> String {
+    let run = &instance.runs[run_index];
+    match run.kind {
+        RunKind::Learner => format!(
+            "{}.{}",
+            instance.runners[run.runner_index].name, instance.tasks[run.task_index].name
+        ),
+        RunKind::Solver {
+            problem_index,
+            depends: _,
+        } => format!(
+            "{}.{}.{}",
+            instance.runners[run.runner_index].name,
+            instance.tasks[run.task_index].name,
+            instance.tasks[run.task_index].solve[problem_index]
+        ),
     }
 }
@@ -0,0 +1,162 @@
+use super::{run_name, Progress};
+use crate::evaluation::{read_exit_code, Outcome};
+use crate::misc::logging::{self, ProgressBar};
+use crate::misc::metrics;
+use crate::setup::instance::{Instance, RunKind};
+use pretty_duration::pretty_duration;
+use std::time::Duration;
+
+/// Runs shown by one progress bar, such as those of a stage or runner
+struct Group {
+    label: String,
+    runs: usize,
+    bar: ProgressBar,
+    finished: usize,
+    failed: usize,
+    /// Runs skipped as their solver gave up, which are not counted as failed
+    skipped: usize,
+    /// Number and summed run time of finished runs with a known run time
+    timed: usize,
+    time: f64,
+}
+
+/// Shows the progress of an instance as one bar per stage, or per runner
+pub(super) struct Monitor {
+    groups: Vec<Group>,
+    /// Group of each run
+    group_of: Vec<usize>,
+    /// Whether each run was seen as finished
+    seen: Vec<bool>,
+}
+
+impl Monitor {
+    pub fn new(instance: &Instance, per_runner: bool) -> Self {
+        let label = |i: usize| {
+            let run = &instance.runs[i];
+            match (per_runner, &run.kind) {
+                (true, _) => instance.runners[run.runner_index].name.to_owned(),
+                (false, RunKind::Learner) => "learn".to_owned(),
+                (false, RunKind::Solver { .. }) => "solve".to_owned(),
+            }
+        };
+        // Learners come before solvers, so groups follow the order of stages
+        let mut labels: Vec<String> = vec![];
+        let mut group_of = vec![];
+        for i in 0..instance.runs.len() {
+            let label = label(i);
+            let group = match labels.iter().position(|l| *l == label) {
+                Some(group) => group,
+                None => {
+                    labels.push(label);
+                    labels.len() - 1
+                }
+            };
+            group_of.push(group);
+        }
+        let width = labels.iter().map(|l| l.len()).max().unwrap_or(0);
+        let groups = labels
+            .into_iter()
+            .enumerate()
+            .map(|(g, label)| {
+                let runs = group_of.iter().filter(|o| **o == g).count();
+                let label = format!("{:>width$}", label, width = width);
+                Group {
+                    bar: ProgressBar::new(runs, &label),
+                    label,
+                    runs,
+                    finished: 0,
+                    failed: 0,
+                    skipped: 0,
+                    timed: 0,
+                    time: 0.0,
+                }
+            })
+            .collect();
+        Self {
+            groups,
+            group_of,
+            seen: vec![false; instance.runs.len()],
+        }
+    }
+
+    pub fn update(&mut self, instance: &Instance, progress: &Progress) {
+        for i in progress.finished.iter().cloned() {
+            if self.seen[i] {
+                continue;
+            }
+            self.seen[i] = true;
+            let group = &mut self.groups[self.group_of[i]];
+            let dir = &instance.runs[i].dir;
+            group.finished += 1;
+            let exit_code = read_exit_code(dir);
+            match Outcome::of(dir, exit_code) {
+                Outcome::Skipped => group.skipped += 1,
+                _ if exit_code != Some(0) => group.failed += 1,
+                _ => {}
+            }
+            if let Some(time) = metrics::read(dir)
+                .get(metrics::RUN_TIME)
+                .and_then(|t| t.parse::<f64>().ok())
+            {
+                group.timed += 1;
+                group.time += time;
+            }
+        }
+        for (g, group) in self.groups.iter().enumerate() {
+            let running: Vec<String> = progress
+                .running
+                .iter()
+                .filter(|i| self.group_of[**i] == g)
+                .map(|i| run_name(instance, *i))
+                .collect();
+            let mut msg = format!("{} running, {} failed", running.len(), group.failed);
+            if group.skipped > 0 {
+                msg.push_str(&format!(", {} skipped", group.skipped));
+            }
+            let remaining = group.runs - group.finished;
+            if remaining > 0 && group.timed > 0 {
+                // Runs of the group are assumed to keep running as parallel
+                // as they are now
+                let eta = group.time / group.timed as f64 * remaining as f64
+                    / running.len().max(1) as f64;
+                msg.push_str(&format!(
+                    ", eta {}",
+                    pretty_duration(&Duration::from_secs(eta.round() as u64), None)
+                ));
+            }
+            if !running.is_empty() {
+                let width = logging::width()
+                    .saturating_sub(ProgressBar::width(group.runs, &group.label) + msg.len() + 2);
+                msg.push_str(&format!(": {}", condense(&running, width)));
+            }
+            group.bar.set(group.finished);
+            group.bar.msg(msg);
+        }
+    }
+}
+
+/// Joins names, leaving out those that do not fit within width
+fn condense(names: &[String], width: usize) -> String {
+    let mut condensed = String::new();
+    for (i, name) in names.iter().enumerate() {
+        let separator = match i {
+            0 => "",
+            _ => ", ",
+        };
+        let rest = names.len() - i - 1;
+        let suffix = match rest {
+            0 => 0,
+            _ => format!(", +{} more", rest).len(),
+        };
+        if condensed.len() + separator.len() + name.len() + suffix > width {
+            let more = format!("+{} more", names.len() - i);
+            return match condensed.is_empty() {
+                true => more,
+                false => format!("{}, {}", condensed, more),
+            };
+        }
+        condensed.push_str(separator);
+        condensed.push_str(name);
+    }
+    condensed
+}
@@ -0,0 +1,98 @@
+use anyhow::{bail, Result};
+use log::warn;
+use std::fs;
+
+/// Parses a cpu list such as "0-3,8,10-11"
+pub fn parse_cpu_list(s: &str) -> Result<Vec<usize>> {
+    let mut cpus = vec![];
+    for part in s.split(',').map(|p| p.trim()).filter(|p| !p.is_empty()) {
+        match part.split_once('-') {
+            Some((first, last)) => {
+                let first: usize = first.trim().parse()?;
+                let last: usize = last.trim().parse()?;
+                if last < first {
+                    bail!("Invalid cpu range {}", part);
+                }
+                cpus.extend(first..=last);
+            }
+            None => cpus.push(part.parse()?),
+        }
+    }
+    cpus.sort();
+    cpus.dedup();
+    Ok(cpus)
+}
+
+/// Removes cpus that are hyperthread siblings of an earlier cpu in the list
+pub fn without_siblings(cpus: &[usize]) -> Vec<usize> {
+    let mut kept: Vec<usize> = vec![];
+    for cpu in cpus.iter() {
+        let path = format!(
+            "/sys/devices/system/cpu/cpu{}/topology/thread_siblings_list",
+            cpu
+        );
+        let siblings = match fs::read_to_string(&path)
+            .ok()
+            .and_then(|s| parse_cpu_list(s.trim()).ok())
+        {
+            Some(siblings) => siblings,
+            None => {
+                warn!("Could not read siblings of cpu {}", cpu);
+                vec![*cpu]
+            }
+        };
+        if !kept.iter().any(|k| siblings.contains(k)) {
+            kept.push(*cpu);
+        }
+    }
+    kept
+}
+
+/// Splits cpus into disjoint sets of size per_run, one for each worker
+/// Fails if there are too few cpus for a single set
+pub fn cpu_sets(cpus: &[usize], per_run: usize) -> Result<Vec<Vec<usize>>> {
+    let per_run = per_run.max(1);
+    if cpus.len() < per_run {
+        bail!(
+            "Only {} cpu(s) available, fewer than the {} cpu(s) per run",
+            cpus.len(),
+            per_run
+        );
+    }
+    Ok(cpus.chunks_exact(per_run).map(|c| c.to_vec()).collect())
+}
+
+/// Formats cpus as a list accepted by taskset
+pub fn format_cpu_list(cpus: &[usize]) -> String {
+    cpus.iter()
+        .map(|c| c.to_string())
+        .collect::<Vec<String>>()
+        .join(",")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_cpu_lists() -> Result<()> {
+        assert_eq!(parse_cpu_list("0-3,8,10-11")?, [0, 1, 2, 3, 8, 10, 11]);
+        assert_eq!(parse_cpu_list(" 5 , 2-2,")?, [2, 5]);
+        // Duplicates are removed and the list is sorted
+        assert_eq!(parse_cpu_list("4,1,0-2,2")?, [0, 1, 2, 4]);
+        assert!(parse_cpu_list("3-1").is_err());
+        assert!(parse_cpu_list("0-a").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn splits_cpus_into_sets() -> Result<()> {
+        let cpus: Vec<usize> = (0..5).collect();
+        assert_eq!(cpu_sets(&cpus, 2)?, [vec![0, 1], vec![2, 3]]);
+        assert_eq!(cpu_sets(&cpus, 0)?.len(), 5);
+        assert_eq!(cpu_sets(&cpus, 5)?, [cpus.to_owned()]);
+        assert!(cpu_sets(&cpus, 6).is_err());
+        assert!(cpu_sets(&[], 1).is_err());
+        Ok(())
+    }
+}
@@ -0,0 +1,421 @@
+use crate::evaluation::read_exit_code;
+use crate::setup::instance::{Instance, RunKind, SKIPPED_FILE};
+use log::{info, warn};
+use std::cmp::Reverse;
+use std::collections::{BTreeSet, HashMap};
+use std::fs;
+use std::path::PathBuf;
+use std::sync::{Condvar, Mutex};
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum State {
+    /// Waiting on the learner it depends on
+    Blocked,
+    Ready,
+    Running,
+    Done,
+}
+
+/// Solver runs of a runner on a task in problem order, the remainder of which
+/// are skipped after a number of consecutive failures
+struct Sequence {
+    name: String,
+    runs: Vec<usize>,
+    give_up_after: usize,
+    /// Exit codes counting as failures
+    give_up_on: Vec<i32>,
+    /// Whether each run failed, None while unknown
+    failed: Vec<Option<bool>>,
+    given_up: bool,
+}
+
+struct Queue {
+    dirs: Vec<PathBuf>,
+    states: Vec<State>,
+    /// Ready runs, highest priority first
+    ready: BTreeSet<(Reverse<u64>, usize)>,
+    /// Expected time in ms until all runs depending on each run are done
+    priority: Vec<u64>,
+    running: BTreeSet<usize>,
+    /// Solver runs depending on each learner run
+    dependents: Vec<Vec<usize>>,
+    /// Runs that are not done
+    remaining: usize,
+    /// Memory limit of each run
+    memory: Vec<usize>,
+    /// Sum of the memory limits of running runs
+    memory_used: usize,
+    memory_budget: Option<usize>,
+    sequences: Vec<Sequence>,
+    /// Sequence and position therein of each run that is part of one
+    positions: Vec<Option<(usize, usize)>>,
+    cancelled: bool,
+}
+
+impl Queue {
+    /// Takes the ready run of highest priority whose memory fits in the budget
+    /// A run exceeding the budget by itself is only admitted when no other
+    /// runs are running
+    fn admissible(&mut self) -> Option<usize> {
+        let entry = self
+            .ready
+            .iter()
+            .find(|(_, i)| match self.memory_budget {
+                None => true,
+                Some(budget) => {
+                    self.memory_used + self.memory[*i] <= budget || self.running.is_empty()
+                }
+            })
+            .cloned();
+        entry.map(|entry| {
+            self.ready.remove(&entry);
+            entry.1
+        })
+    }
+
+    fn push_ready(&mut self, i: usize) {
+        self.states[i] = State::Ready;
+        self.ready.insert((Reverse(self.priority[i]), i));
+    }
+
+    /// Records the outcome of a done run, giving up on its sequence once it
+    /// has enough consecutive failures
+    /// Returns the runs skipped as a result
+    fn record(&mut self, i: usize) -> Vec<usize> {
+        let Some((s, position)) = self.positions[i] else {
+            return vec![];
+        };
+        let exit_code = read_exit_code(&self.dirs[i]);
+        let sequence = &mut self.sequences[s];
+        sequence.failed[position] =
+            Some(exit_code.is_some_and(|c| sequence.give_up_on.contains(&c)));
+        if sequence.given_up || self.cancelled {
+            return vec![];
+        }
+        let mut streak = 0;
+        let end = sequence.failed.iter().position(|failed| {
+            streak = match failed {
+                Some(true) => streak + 1,
+                _ => 0,
+            };
+            streak >= sequence.give_up_after
+        });
+        let Some(end) = end else {
+            return vec![];
+        };
+        sequence.given_up = true;
+        let skipped: Vec<usize> = sequence.runs[end + 1..]
+            .iter()
+            .filter(|r| matches!(self.states[**r], State::Ready | State::Blocked))
+            .cloned()
+            .collect();
+        info!(
+            "Giving up on {} after {} consecutive failures, skipping {} run(s)",
+            sequence.name,
+            sequence.give_up_after,
+            skipped.len()
+        );
+        for r in skipped.iter().cloned() {
+            self.ready.remove(&(Reverse(self.priority[r]), r));
+            self.states[r] = State::Done;
+            self.remaining -= 1;
+            let _ = fs::write(self.dirs[r].join(SKIPPED_FILE), "");
+        }
+        skipped
+    }
+}
+
+/// Hands out runs to workers once their dependencies are done
+pub(super) struct Scheduler {
+    queue: Mutex<Queue>,
+    cvar: Condvar,
+}
+
+impl Scheduler {
+    /// Runs are only started if their memory fits within the budget, along
+    /// with that of running runs
+    /// Of those ready, runs are started longest expected first, where learners
+    /// include the longest of the solvers depending on them
+    /// Solvers with an early termination policy have their remaining runs on a
+    /// task skipped once they fail enough consecutive problems
+    pub fn new(
+        instance: &Instance,
+        estimates: &[f64],
+        memory: Vec<usize>,
+        memory_budget: Option<usize>,
+    ) -> Self {
+        if let Some(budget) = memory_budget {
+            if memory.iter().any(|m| *m > budget) {
+                warn!(
+                    "Runs with a memory limit above {}MB are run without others alongside",
+                    budget
+                );
+            }
+        }
+        let runs = &instance.runs;
+        let mut dependents = vec![vec![]; runs.len()];
+        let mut states = vec![State::Ready; runs.len()];
+        for (i, run) in runs.iter().enumerate() {
+            if run.skip {
+                states[i] = State::Done;
+            } else if let RunKind::Solver {
+                depends: Some(depends),
+                ..
+            } = run.kind
+            {
+                if !runs[depends].skip {
+                    states[i] = State::Blocked;
+                    dependents[depends].push(i);
+                }
+            }
+        }
+        let priority: Vec<u64> = (0..runs.len())
+            .map(|i| {
+                let dependents = dependents[i]
+                    .iter()
+                    .map(|d| estimates[*d])
+                    .fold(0.0, f64::max);
+                // Saturates for runs of unknown length without a time limit
+                ((estimates[i] + dependents) * 1000.0) as u64
+            })
+            .collect();
+        let ready = (0..runs.len())
+            .filter(|i| states[*i] == State::Ready)
+            .map(|i| (Reverse(priority[i]), i))
+            .collect();
+        let remaining = states.iter().filter(|s| **s != State::Done).count();
+        // Solver runs are ordered by task, then problem
+        let mut sequences: Vec<Sequence> = vec![];
+        let mut indices: HashMap<(usize, usize), usize> = HashMap::new();
+        let mut positions = vec![None; runs.len()];
+        for (i, run) in runs.iter().enumerate() {
+            if let Some(give_up_after) = instance.give_up_after(i) {
+                let s = *indices
+                    .entry((run.runner_index, run.task_index))
+                    .or_insert_with(|| {
+                        sequences.push(Sequence {
+                            name: format!(
+                                "{}.{}",
+                                instance.runners[run.runner_index].name,
+                                instance.tasks[run.task_index].name
+                            ),
+                            runs: vec![],
+                            give_up_after,
+                            give_up_on: instance.give_up_on(i).to_vec(),
+                            failed: vec![],
+                            given_up: false,
+                        });
+                        sequences.len() - 1
+                    });
+                positions[i] = Some((s, sequences[s].runs.len()));
+                sequences[s].runs.push(i);
+                sequences[s].failed.push(None);
+            }
+        }
+        let mut queue = Queue {
+            dirs: runs.iter().map(|r| r.dir.to_owned()).collect(),
+            states,
+            ready,
+            priority,
+            running: BTreeSet::new(),
+            dependents,
+            remaining,
+            memory,
+            memory_used: 0,
+            memory_budget,
+            sequences,
+            positions,
+            cancelled: false,
+        };
+        // Failures of a prior run count towards giving up
+        for (i, run) in runs.iter().enumerate() {
+            if run.skip {
+                queue.record(i);
+            }
+        }
+        Self {
+            queue: Mutex::new(queue),
+            cvar: Condvar::new(),
+        }
+    }
+
+    /// Blocks until a run is ready and marks it as running
+    /// Returns None once all runs are done or scheduling is cancelled
+    pub fn next(&self) -> Option<usize> {
+        let mut queue = self.queue.lock().unwrap();
+        loop {
+            if queue.cancelled || queue.remaining == 0 {
+                return None;
+            }
+            if let Some(i) = queue.admissible() {
+                queue.states[i] = State::Running;
+                queue.running.insert(i);
+                queue.memory_used += queue.memory[i];
+                return Some(i);
+            }
+            queue = self.cvar.wait(queue).unwrap();
+        }
+    }
+
+    /// Marks run as done, releasing the runs depending on it
+    /// Returns the runs skipped as its solver gave up on the task
+    pub fn finish(&self, i: usize) -> Vec<usize> {
+        let mut queue = self.queue.lock().unwrap();
+        queue.states[i] = State::Done;
+        queue.running.remove(&i);
+        queue.memory_used -= queue.memory[i];
+        queue.remaining -= 1;
+        for dependent in std::mem::take(&mut queue.dependents[i]) {
+            // Unless it was skipped meanwhile
+            if queue.states[dependent] == State::Blocked {
+                queue.push_ready(dependent);
+            }
+        }
+        let skipped = queue.record(i);
+        self.cvar.notify_all();
+        skipped
+    }
+
+    /// Stops handing out runs
+    pub fn cancel(&self) {
+        self.queue.lock().unwrap().cancelled = true;
+        self.cvar.notify_all();
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.queue.lock().unwrap().cancelled
+    }
+
+    /// Runs currently running, and those that are done
+    pub fn progress(&self) -> (Vec<usize>, Vec<usize>) {
+        let queue = self.queue.lock().unwrap();
+        (
+            queue.running.iter().cloned().collect(),
+            (0..queue.states.len())
+                .filter(|i| queue.states[*i] == State::Done)
+                .collect(),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::setup::{instance, suite};
+    use anyhow::Result;
+
+    /// Instance of a suite with the given runners on one task of four
+    /// problems, generated in dir
+    fn instance(dir: &tempfile::TempDir, runners: &str) -> Result<Instance> {
+        let base = dir.path();
+        for file in ["domain.pddl", "p1.pddl", "p2.pddl", "p3.pddl", "p4.pddl"] {
+            fs::write(base.join(file), "")?;
+        }
+        let content = format!(
+            "{}\n[[tasks]]\nname = \"t\"\ndomain = \"domain.pddl\"\n\
+             learn = [\"p1.pddl\"]\nsolve = [\"p*.pddl\"]\n",
+            runners
+        );
+        let suite = suite::parse(&content, base)?;
+        instance::generate(&base.join("work"), suite, &Default::default())
+    }
+
+    #[test]
+    fn releases_solvers_once_their_learner_is_done() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let instance = instance(
+            &dir,
+            "[[runners]]\nname = \"l\"\npath = \"l.sh\"\nkind = \"Learn\"\n\
+             [[runners]]\nname = \"s\"\npath = \"s.sh\"\nkind = \"Solve\"\ndepends = \"l\"\n",
+        )?;
+        let runs = instance.runs.len();
+        assert_eq!(runs, 5);
+        let scheduler = Scheduler::new(&instance, &vec![0.0; runs], vec![0; runs], None);
+        assert_eq!(scheduler.next(), Some(0));
+        assert_eq!(scheduler.queue.lock().unwrap().admissible(), None);
+        assert!(scheduler.finish(0).is_empty());
+        let mut solvers: Vec<usize> = (1..runs).map(|_| scheduler.next().unwrap()).collect();
+        solvers.sort();
+        assert_eq!(solvers, [1, 2, 3, 4]);
+        assert_eq!(scheduler.progress(), (vec![1, 2, 3, 4], vec![0]));
+        for i in solvers {
+            scheduler.finish(i);
+        }
+        assert_eq!(scheduler.next(), None);
+        Ok(())
+    }
+
+    #[test]
+    fn admits_runs_within_the_memory_budget() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let instance = instance(
+            &dir,
+            "[[runners]]\nname = \"s\"\npath = \"s.sh\"\nkind = \"Solve\"\n",
+        )?;
+        // Longest expected first
+        let estimates = [4.0, 3.0, 2.0, 1.0];
+        let scheduler =
+            Scheduler::new(&instance, &estimates, vec![600, 600, 300, 2000], Some(1000));
+        assert_eq!(scheduler.next(), Some(0));
+        // Run 1 does not fit alongside run 0, while run 2 does
+        assert_eq!(scheduler.next(), Some(2));
+        assert_eq!(scheduler.queue.lock().unwrap().admissible(), None);
+        scheduler.finish(0);
+        assert_eq!(scheduler.next(), Some(1));
+        scheduler.finish(1);
+        scheduler.finish(2);
+        // Exceeding the budget by itself, run 3 is admitted once alone
+        assert_eq!(scheduler.next(), Some(3));
+        scheduler.finish(3);
+        assert_eq!(scheduler.next(), None);
+        Ok(())
+    }
+
+    #[test]
+    fn gives_up_after_consecutive_failures() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let instance = instance(
+            &dir,
+            "[[runners]]\nname = \"s\"\npath = \"s.sh\"\nkind = \"Solve\"\ngive_up_after = 2\n",
+        )?;
+        let scheduler = Scheduler::new(&instance, &[0.0; 4], vec![0; 4], None);
+        let fail = |i: usize| fs::write(instance.runs[i].dir.join("exit_code"), "124\n");
+        assert_eq!(scheduler.next(), Some(0));
+        fail(0)?;
+        assert!(scheduler.finish(0).is_empty());
+        assert_eq!(scheduler.next(), Some(1));
+        fail(1)?;
+        assert_eq!(scheduler.finish(1), [2, 3]);
+        for i in [2, 3] {
+            assert!(instance.runs[i].dir.join(SKIPPED_FILE).exists());
+        }
+        assert_eq!(scheduler.next(), None);
+        assert_eq!(scheduler.progress(), (vec![], vec![0, 1, 2, 3]));
+        Ok(())
+    }
+    #[test]
+    fn gives_up_only_on_configured_exit_codes() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let runner = "[[runners]]\nname = \"s\"\npath = \"s.sh\"\nkind = \"Solve\"\n\
+                      give_up_after = 2\n";
+        let first = instance(&dir, runner)?;
+        let scheduler = Scheduler::new(&first, &[0.0; 4], vec![0; 4], None);
+        // Plain failures are neither timeouts nor kills
+        for i in 0..4 {
+            assert_eq!(scheduler.next(), Some(i));
+            fs::write(first.runs[i].dir.join("exit_code"), "1\n")?;
+            assert!(scheduler.finish(i).is_empty());
+        }
+
+        let dir = tempfile::tempdir()?;
+        let instance = instance(&dir, &format!("{}give_up_on = [1]\n", runner))?;
+        let scheduler = Scheduler::new(&instance, &[0.0; 4], vec![0; 4], None);
+        for i in 0..2 {
+            assert_eq!(scheduler.next(), Some(i));
+            fs::write(instance.runs[i].dir.join("exit_code"), "1\n")?;
+        }
+        assert!(scheduler.finish(0).is_empty());
+        assert_eq!(scheduler.finish(1), [2, 3]);
+        Ok(())
+    }
+}
@@ -1,98 +1,501 @@
+use super::{events, Executor, Progress};
 use crate::setup::instance::{Instance, RunKind};
-use anyhow::Result;
-use log::info;
-use std::io::Write;
-use std::path::PathBuf;
-use std::process::{Command, Output};
-use tempfile::NamedTempFile;
-
-pub fn execute(instance: Instance) -> Result<()> {
-    if instance
-        .runs
-        .iter()
-        .any(|r| r.kind == RunKind::Learner && !r.skip)
-    {
-        info!("Running learn");
-        let executer = generate_executer(&instance.learn_dir, instance.learn_mem_limit)?;
-        println!(
-            "{:?}",
-            execute_learn(&instance, &executer.path().to_path_buf())
+use crate::setup::suite::SlurmOptions;
+use anyhow::{bail, Context, Result};
+use log::{info, trace, warn};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fmt::Write;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::thread::sleep;
+use std::time::Duration;
+
+/// Default of MaxArraySize in slurm.conf
+const DEFAULT_MAX_ARRAY_SIZE: usize = 1001;
+/// Time between checking on submitted jobs
+const POLL_INTERVAL: Duration = Duration::from_secs(10);
+
+/// File in the work dir listing the ids of submitted jobs
+pub const JOBS_FILE: &str = "slurm_jobs";
+/// Job states after which a job no longer changes
+const TERMINAL_STATES: [&str; 10] = [
+    "COMPLETED",
+    "FAILED",
+    "CANCELLED",
+    "TIMEOUT",
+    "OUT_OF_MEMORY",
+    "NODE_FAIL",
+    "PREEMPTED",
+    "BOOT_FAIL",
+    "DEADLINE",
+    "REVOKED",
+];
+
+/// Executes runs as Slurm job arrays
+pub struct SlurmExecutor {
+    poll_interval: Duration,
+    job_ids: Vec<String>,
+    /// Runs handled by each array element, such as 123_4
+    elements: HashMap<String, Vec<usize>>,
+    /// Runs seen running or done, and those seen done
+    started: HashSet<usize>,
+    finished: HashSet<usize>,
+    stages: events::Stages,
+}
+
+impl Default for SlurmExecutor {
+    fn default() -> Self {
+        Self::new(POLL_INTERVAL)
+    }
+}
+
+impl SlurmExecutor {
+    /// Executor checking on submitted jobs every poll_interval
+    pub fn new(poll_interval: Duration) -> Self {
+        Self {
+            poll_interval,
+            job_ids: vec![],
+            elements: HashMap::new(),
+            started: HashSet::new(),
+            finished: HashSet::new(),
+            stages: Default::default(),
+        }
+    }
+}
+
+impl Executor for SlurmExecutor {
+    fn submit(&mut self, instance: &Instance) -> Result<()> {
+        if (0..instance.runs.len()).any(|i| instance.give_up_after(i).is_some()) {
+            warn!(
+                "Giving up after consecutive failures is not supported by slurm, \
+                 all runs are submitted"
+            );
+        }
+        // Array element of each submitted learner run
+        let mut learner_jobs: HashMap<usize, String> = HashMap::new();
+        let learn: Vec<usize> = (0..instance.runs.len())
+            .filter(|i| instance.runs[*i].kind == RunKind::Learner && !instance.runs[*i].skip)
+            .collect();
+        if !learn.is_empty() {
+            info!("Submitting learn");
+            let executer = generate_executer(
+                &instance.learn_dir,
+                instance.learn_mem_limit,
+                &instance.learn_slurm,
+                "P10_Meta_Learn",
+            )?;
+            let mut counter = 0;
+            for (job_id, runs) in submit(
+                instance,
+                &instance.learn_dir,
+                &learn,
+                &instance.learn_slurm,
+                &executer,
+                None,
+                &mut counter,
+            )? {
+                for (i, run) in runs.into_iter().enumerate() {
+                    events::queued(instance, run);
+                    let element = format!("{}_{}", job_id, i / instance.learn_slurm.runs_per_job());
+                    learner_jobs.insert(run, element.to_owned());
+                    self.elements.entry(element).or_default().push(run);
+                }
+                self.job_ids.push(job_id);
+            }
+        }
+        // Solve runs grouped by the learner job they wait on
+        let mut solve: BTreeMap<Option<String>, Vec<usize>> = BTreeMap::new();
+        for (i, run) in instance.runs.iter().enumerate() {
+            if let RunKind::Solver { depends, .. } = run.kind {
+                if run.skip {
+                    continue;
+                }
+                let dependency = depends.and_then(|d| learner_jobs.get(&d).cloned());
+                solve.entry(dependency).or_default().push(i);
+            }
+        }
+        if !solve.is_empty() {
+            info!("Submitting solve");
+            let executer = generate_executer(
+                &instance.solve_dir,
+                instance.solve_mem_limit,
+                &instance.solve_slurm,
+                "P10_Meta_Solve",
+            )?;
+            let mut counter = 0;
+            for (dependency, runs) in solve.into_iter() {
+                for (job_id, runs) in submit(
+                    instance,
+                    &instance.solve_dir,
+                    &runs,
+                    &instance.solve_slurm,
+                    &executer,
+                    dependency.map(|d| format!("afterany:{}", d)),
+                    &mut counter,
+                )? {
+                    for (i, run) in runs.into_iter().enumerate() {
+                        events::queued(instance, run);
+                        let element =
+                            format!("{}_{}", job_id, i / instance.solve_slurm.runs_per_job());
+                        self.elements.entry(element).or_default().push(run);
+                    }
+                    self.job_ids.push(job_id);
+                }
+            }
+        }
+        info!("Submitted {} job(s)", self.job_ids.len());
+        Ok(())
+    }
+
+    fn poll(&mut self, instance: &Instance) -> Result<Progress> {
+        let finished = || {
+            instance
+                .runs
+                .iter()
+                .enumerate()
+                .filter(|(_, r)| r.skip || r.dir.join("exit_code").exists())
+                .map(|(i, _)| i)
+                .collect()
+        };
+        if self.job_ids.is_empty() {
+            return Ok(Progress {
+                running: vec![],
+                finished: finished(),
+                done: true,
+            });
+        }
+        sleep(self.poll_interval);
+        // Jobs keep running if they cannot be checked on, so try again later
+        let (tasks, done) = match states(&self.job_ids) {
+            Ok(states) => states,
+            Err(err) => {
+                warn!("Could not check on jobs, retrying: {:#}", err);
+                (vec![], false)
+            }
+        };
+        let running: Vec<usize> = tasks
+            .iter()
+            .filter(|(_, state)| state == "RUNNING")
+            .filter_map(|(id, _)| self.elements.get(id))
+            .flatten()
+            .cloned()
+            .collect();
+        let finished = finished();
+        for i in running.iter().cloned() {
+            if self.started.insert(i) {
+                events::started(instance, &self.stages, i);
+            }
+        }
+        for i in finished.iter().cloned() {
+            if instance.runs[i].skip || !self.finished.insert(i) {
+                continue;
+            }
+            // Runs may start and finish between polls
+            if self.started.insert(i) {
+                events::started(instance, &self.stages, i);
+            }
+            events::finished(instance, i);
+        }
+        Ok(Progress {
+            running,
+            finished,
+            done,
+        })
+    }
+
+    fn cancel(&mut self, _instance: &Instance) -> Result<()> {
+        if self.job_ids.is_empty() {
+            return Ok(());
+        }
+        let mut command = Command::new("scancel");
+        command.args(&self.job_ids);
+        trace!("Running command: {:?}", command);
+        command.status()?;
+        Ok(())
+    }
+
+    fn detachable(&self) -> bool {
+        true
+    }
+
+    /// Writes the ids of the submitted jobs to the work dir
+    fn detach(&mut self, instance: &Instance) -> Result<()> {
+        let jobs_file = instance.work_dir.join(JOBS_FILE);
+        fs::write(
+            jobs_file,
+            self.job_ids
+                .iter()
+                .map(|id| format!("{}\n", id))
+                .collect::<String>(),
+        )?;
+        Ok(())
+    }
+}
+
+/// Checks on the jobs submitted by a detached run in work dir
+/// Returns whether all of them are done
+pub fn collect(work_dir: &Path) -> Result<bool> {
+    let job_ids: Vec<String> = fs::read_to_string(work_dir.join(JOBS_FILE))?
+        .lines()
+        .map(|l| l.trim().to_owned())
+        .filter(|l| !l.is_empty())
+        .collect();
+    let (tasks, done) = states(&job_ids)?;
+    if !done {
+        info!(
+            "{} array task(s) still pending or running",
+            tasks
+                .iter()
+                .filter(|(_, state)| !TERMINAL_STATES.contains(&state.as_str()))
+                .count()
         );
+        return Ok(false);
     }
-    if instance
-        .runs
-        .iter()
-        .any(|r| r.kind != RunKind::Learner && !r.skip)
-    {
-        info!("Running solve");
-        let executer = generate_executer(&instance.solve_dir, instance.solve_mem_limit)?;
-        println!(
-            "{:?}",
-            execute_solve(&instance, &executer.path().to_path_buf())
+    let mut states: BTreeMap<&str, usize> = BTreeMap::new();
+    for (_, state) in tasks.iter() {
+        *states.entry(state).or_default() += 1;
+    }
+    for (state, count) in states.iter() {
+        match *state {
+            "COMPLETED" => info!("{} array task(s) {}", count, state),
+            _ => warn!("{} array task(s) {}", count, state),
+        }
+    }
+    Ok(true)
+}
+
+/// Submits the runs as job arrays of at most MaxArraySize elements
+/// Each array is given an index map, whose n'th line is the dir of the n'th
+/// run, where array element i handles runs i * k to (i + 1) * k - 1 for k runs
+/// per job
+/// Returns the job id of each array along with the runs it handles
+fn submit(
+    instance: &Instance,
+    dir: &Path,
+    runs: &[usize],
+    options: &SlurmOptions,
+    executer: &PathBuf,
+    dependency: Option<String>,
+    counter: &mut usize,
+) -> Result<Vec<(String, Vec<usize>)>> {
+    let max_array_size = match options.max_array_size {
+        Some(size) => size,
+        None => max_array_size(),
+    };
+    let runs_per_job = options.runs_per_job();
+    let mut jobs = vec![];
+    for chunk in runs.chunks(max_array_size * runs_per_job) {
+        let elements = chunk.len().div_ceil(runs_per_job);
+        let index_map = dir.join(format!("array.{}", counter));
+        *counter += 1;
+        fs::write(
+            &index_map,
+            chunk
+                .iter()
+                .map(|r| format!("{}\n", instance.runs[*r].dir.to_string_lossy()))
+                .collect::<String>(),
+        )?;
+        let array = match options.throttle {
+            Some(throttle) => format!("--array=0-{}%{}", elements - 1, throttle),
+            None => format!("--array=0-{}", elements - 1),
+        };
+        let mut command = Command::new("sbatch");
+        // Slurm writes the output of jobs to the dir they are submitted from
+        command.current_dir(&instance.work_dir);
+        command.args(["--parsable", &array]);
+        if let Some(dependency) = &dependency {
+            command.arg(format!("--dependency={}", dependency));
+        }
+        command.arg(executer).arg(&index_map);
+        trace!("Running command: {:?}", command);
+        let output = command.output()?;
+        if !output.status.success() {
+            bail!(
+                "sbatch failed: {}",
+                String::from_utf8_lossy(&output.stderr).trim()
+            );
+        }
+        // --parsable gives either "id" or "id;cluster"
+        let job_id = String::from_utf8_lossy(&output.stdout)
+            .trim()
+            .split(';')
+            .next()
+            .unwrap_or_default()
+            .to_owned();
+        trace!("Submitted job {}", job_id);
+        jobs.push((job_id, chunk.to_vec()));
+    }
+    Ok(jobs)
+}
+
+/// Array tasks of the jobs along with their state, and whether all jobs are
+/// done
+/// Falls back to squeue if sacct fails, such as without accounting, which only
+/// lists jobs that are not done
+fn states(job_ids: &[String]) -> Result<(Vec<(String, String)>, bool)> {
+    match sacct(job_ids) {
+        Ok(tasks) => {
+            let done = done(job_ids, &tasks);
+            Ok((tasks, done))
+        }
+        Err(err) => {
+            trace!("{:#}, falling back to squeue", err);
+            let tasks = squeue(job_ids).with_context(|| format!("{:#}", err))?;
+            let done = tasks.is_empty();
+            Ok((tasks, done))
+        }
+    }
+}
+
+/// Array tasks of the jobs known to sacct, along with their state
+/// Tasks of an array that are still pending may be listed as one, such as
+/// 123_[4-7], and job steps such as 123_4.batch are left out
+fn sacct(job_ids: &[String]) -> Result<Vec<(String, String)>> {
+    if job_ids.is_empty() {
+        return Ok(vec![]);
+    }
+    let mut command = Command::new("sacct");
+    command.args([
+        "--noheader",
+        "--parsable2",
+        "--format=JobID,State",
+        "--jobs",
+        &job_ids.join(","),
+    ]);
+    trace!("Running command: {:?}", command);
+    let output = command.output().context("Failed to run sacct")?;
+    if !output.status.success() {
+        bail!(
+            "sacct failed: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
         );
     }
-    Ok(())
+    Ok(String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .filter_map(|l| l.trim().split_once('|'))
+        .filter(|(id, _)| !id.contains('.'))
+        // States such as "CANCELLED by 123" name who caused them
+        .map(|(id, state)| {
+            let state = state.split_whitespace().next().unwrap_or_default();
+            (id.to_owned(), state.to_owned())
+        })
+        .collect())
 }
 
-fn execute_learn(instance: &Instance, executer: &PathBuf) -> Result<Output> {
-    let array = format!(
-        "--array=0-{}",
-        instance
-            .runs
-            .iter()
-            .filter(|r| r.kind == RunKind::Learner)
-            .count()
-            - 1
-    );
-    Ok(Command::new("sbatch")
-        .args([
-            "--wait",
-            &array,
-            "--job-name=P10_Meta_Learn",
-            &executer.to_string_lossy(),
-        ])
-        .output()?)
+/// Array tasks of the jobs that are pending or running, along with their state
+fn squeue(job_ids: &[String]) -> Result<Vec<(String, String)>> {
+    if job_ids.is_empty() {
+        return Ok(vec![]);
+    }
+    let mut command = Command::new("squeue");
+    command.args([
+        "--noheader",
+        "--array",
+        "--format=%i|%T",
+        "--jobs",
+        &job_ids.join(","),
+    ]);
+    trace!("Running command: {:?}", command);
+    let output = command.output().context("Failed to run squeue")?;
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    // Given only jobs that have left the queue, squeue rejects their ids
+    if !output.status.success() && stderr.contains("Invalid job id") {
+        return Ok(vec![]);
+    }
+    if !output.status.success() {
+        bail!("squeue failed: {}", stderr.trim());
+    }
+    Ok(String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .filter_map(|l| l.trim().split_once('|'))
+        .map(|(id, state)| (id.to_owned(), state.to_owned()))
+        .collect())
 }
 
-fn execute_solve(instance: &Instance, executer: &PathBuf) -> Result<Output> {
-    let array = format!(
-        "--array=0-{}",
-        instance
-            .runs
+/// Whether all jobs are known to sacct with all their tasks in a terminal
+/// state, as jobs may take a moment to show up after submission
+fn done(job_ids: &[String], tasks: &[(String, String)]) -> bool {
+    job_ids.iter().all(|job| {
+        tasks
             .iter()
-            .filter(|r| r.kind != RunKind::Learner)
-            .count()
-            - 1
-    );
-    Ok(Command::new("sbatch")
-        .args([
-            "--wait",
-            &array,
-            "--job-name=P10_Meta_Solve",
-            &executer.to_string_lossy(),
-        ])
-        .output()?)
+            .any(|(id, _)| id == job || id.starts_with(&format!("{}_", job)))
+    }) && tasks
+        .iter()
+        .all(|(_, state)| TERMINAL_STATES.contains(&state.as_str()))
+}
+
+/// Retrieves MaxArraySize from the cluster configuration
+fn max_array_size() -> usize {
+    let output = match Command::new("scontrol").args(["show", "config"]).output() {
+        Ok(output) => String::from_utf8_lossy(&output.stdout).to_string(),
+        Err(_) => "".to_owned(),
+    };
+    output
+        .lines()
+        .find(|l| l.starts_with("MaxArraySize"))
+        .and_then(|l| l.split('=').nth(1))
+        .and_then(|v| v.trim().parse().ok())
+        .unwrap_or(DEFAULT_MAX_ARRAY_SIZE)
 }
 
-fn generate_executer(dir: &PathBuf, mem_limit: Option<usize>) -> Result<NamedTempFile> {
-    let mut file = NamedTempFile::new_in(dir)?;
+fn generate_executer(
+    dir: &Path,
+    mem_limit: Option<usize>,
+    options: &SlurmOptions,
+    job_name: &str,
+) -> Result<PathBuf> {
+    let mut content = String::new();
 
-    let _ = writeln!(file, "#!/bin/bash\n");
+    let _ = writeln!(content, "#!/bin/bash\n");
     let _ = writeln!(
-        file,
-        "#SBATCH --mem={}G\n",
-        match mem_limit {
-            Some(lim) => lim.div_ceil(999),
-            None => 16,
+        content,
+        "#SBATCH --job-name={}",
+        options.job_name.as_deref().unwrap_or(job_name)
+    );
+    let _ = writeln!(
+        content,
+        "#SBATCH --mem={}",
+        match (&options.memory, mem_limit) {
+            (Some(mem), _) => mem.to_owned(),
+            (None, Some(lim)) => format!("{}G", lim.div_ceil(999)),
+            (None, None) => "16G".to_owned(),
         }
     );
+    if let Some(partition) = &options.partition {
+        let _ = writeln!(content, "#SBATCH --partition={}", partition);
+    }
+    if let Some(account) = &options.account {
+        let _ = writeln!(content, "#SBATCH --account={}", account);
+    }
+    if let Some(qos) = &options.qos {
+        let _ = writeln!(content, "#SBATCH --qos={}", qos);
+    }
+    if let Some(cpus) = options.cpus {
+        let _ = writeln!(content, "#SBATCH --cpus-per-task={}", cpus);
+    }
+    if let Some(time) = &options.time {
+        let _ = writeln!(content, "#SBATCH --time={}", time);
+    }
+    if let Some(constraint) = &options.constraint {
+        let _ = writeln!(content, "#SBATCH --constraint={}", constraint);
+    }
+    for extra in options.extra.iter() {
+        let _ = writeln!(content, "#SBATCH {}", extra);
+    }
+    let _ = writeln!(content);
+    let _ = writeln!(content, "RUNS={}", options.runs_per_job());
+    let _ = writeln!(content, "FIRST=$((SLURM_ARRAY_TASK_ID * RUNS + 1))");
+    let _ = writeln!(content, "LAST=$((FIRST + RUNS - 1))\n");
+    // Runners do not read the index map in place of their input
     let _ = writeln!(
-        file,
-        "DIR={}/${{SLURM_ARRAY_TASK_ID}}\n",
-        dir.to_string_lossy()
+        content,
+        "sed -n \"${{FIRST}},${{LAST}}p\" \"$1\" | while IFS= read -r DIR; do"
     );
-    let _ = writeln!(file, "cd ${{DIR}}\n");
-    let _ = writeln!(file, "./runner.sh\n");
+    let _ = writeln!(content, "    (cd \"$DIR\" && ./runner.sh </dev/null)");
+    let _ = writeln!(content, "done");
 
-    Ok(file)
+    let executer = dir.join("executer.sh");
+    fs::write(&executer, content)?;
+    Ok(executer)
 }
@@ -0,0 +1,11 @@
+//! labyr benchmarks PDDL planners and learners
+//!
+//! A [`setup::suite::Suite`] is either parsed from TOML or built directly, and
+//! is turned into an [`setup::instance::Instance`] of runs in a work dir. The
+//! instance is executed by an [`execution::Executor`], after which
+//! [`evaluation::results`] gives its typed results.
+
+pub mod evaluation;
+pub mod execution;
+pub mod misc;
+pub mod setup;
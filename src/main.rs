@@ -1,20 +1,32 @@
-mod evaluation;
-mod execution;
-mod misc;
-mod setup;
-
-use crate::misc::logging;
-use anyhow::Result;
-use clap::Parser;
-use execution::ExecutionKind;
+use anyhow::{bail, Result};
+use clap::{Parser, Subcommand};
+use labyr::execution::{self, ExecutionKind};
+use labyr::misc::{events, logging};
+use labyr::{evaluation, setup};
 use log::{info, trace};
 use path_absolutize::Absolutize;
-use std::{fs, path::PathBuf, thread::available_parallelism};
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    thread::available_parallelism,
+};
 use tempfile::tempdir_in;
 
+/// File in the work dir holding the path of the suite
+const SUITE_FILE: &str = "suite";
+/// File in the work dir holding the content of the suite it was generated from
+const SUITE_SOURCE_FILE: &str = "suite.toml";
+
 #[derive(Parser, Debug)]
-#[command(version)]
+#[command(
+    version,
+    args_conflicts_with_subcommands = true,
+    subcommand_negates_reqs = true
+)]
 struct Args {
+    #[command(subcommand)]
+    command: Option<Command>,
+
     /// Specifies directory wherein work dir will be created
     #[arg(short, long, required = false, default_value = "/tmp")]
     work_dir: PathBuf,
@@ -23,7 +35,8 @@ struct Args {
     #[arg(short, long, required = false, default_value = "results")]
     out: PathBuf,
 
-    /// Whether to keep working dir
+    /// Whether to keep working dir, implied by exporting html, whose report
+    /// links to the files of runs
     #[arg(short, long, required = false)]
     keep_working_dir: bool,
 
@@ -31,6 +44,23 @@ struct Args {
     #[arg(short, long, required = false, default_value_t = 1)]
     threads: usize,
 
+    /// The memory in MB the local runner may use at once, as given by the
+    /// memory limits of running runs. Runs without a limit count as 0
+    #[arg(long)]
+    max_memory: Option<usize>,
+
+    /// Pins each local run to its own cpu(s) from this list, e.g. "0-15"
+    #[arg(long)]
+    cpus: Option<String>,
+
+    /// Given "cpus" the number of cpus each run is pinned to
+    #[arg(long, default_value_t = 1)]
+    cpus_per_run: usize,
+
+    /// Given "cpus" leaves hyperthread siblings of the used cpus idle
+    #[arg(long, default_value = "false")]
+    skip_siblings: bool,
+
     #[arg(short, long, required = false, default_value = "local")]
     execution_kind: ExecutionKind,
 
@@ -47,44 +77,333 @@ struct Args {
     #[arg(long, default_value = "false")]
     force_solve: bool,
 
+    /// Earlier work dirs or results dirs whose run times are used to start
+    /// long runs first. Runs not found in them are assumed to take their time
+    /// limit
+    #[arg(long)]
+    history: Vec<PathBuf>,
+
+    /// Writes run lifecycle events as lines of JSON to this file or FIFO
+    #[arg(long)]
+    events: Option<PathBuf>,
+
+    /// Additional formats to write results in
+    #[arg(long, value_delimiter = ',')]
+    export: Vec<evaluation::export::Format>,
+
+    /// Prepends the runner, task, problem and run dir of each row to the csv
+    /// files collected from run dirs
+    #[arg(long, default_value = "false")]
+    provenance: bool,
+
+    /// Shows a progress bar per runner rather than per stage
+    #[arg(long, default_value = "false")]
+    progress_per_runner: bool,
+
+    /// Submits the jobs and exits without waiting for them, for use with slurm.
+    /// Implies "keep_working_dir", results are then evaluated with "collect"
+    #[arg(long, default_value = "false")]
+    detach: bool,
+
     /// The suite to run
     #[arg(required = true)]
-    suite: PathBuf,
+    suite: Option<PathBuf>,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Evaluates a detached run once all of its jobs are done
+    Collect {
+        /// The work dir of the detached run
+        work_dir: PathBuf,
+
+        /// Specifies which directory results will be written to
+        #[arg(short, long, required = false, default_value = "results")]
+        out: PathBuf,
+
+        /// Additional formats to write results in
+        #[arg(long, value_delimiter = ',')]
+        export: Vec<evaluation::export::Format>,
+
+        /// Prepends the runner, task, problem and run dir of each row to the
+        /// csv files collected from run dirs
+        #[arg(long, default_value = "false")]
+        provenance: bool,
+    },
+    /// Compares two solvers on the problems of a results dir
+    Compare {
+        /// The results dir, or its solve.csv
+        results: PathBuf,
+
+        /// The solver to compare
+        #[arg(long)]
+        a: String,
+
+        /// The solver to compare against, such as a baseline
+        #[arg(long)]
+        b: String,
+
+        /// Attributes to show side by side, the first of which decides which
+        /// solver is better on a problem, run_time by default if measured
+        #[arg(long = "attribute")]
+        attributes: Vec<String>,
+
+        /// Whether higher values of the deciding attribute are better
+        #[arg(long, default_value = "false")]
+        maximize: bool,
+    },
+    /// Writes LaTeX tables of coverage and attributes per domain and solver
+    Latex {
+        /// The results dir, or its solve.csv
+        results: PathBuf,
+
+        /// Attribute to summarize as NAME[:AGGREGATION[:max]], where the
+        /// aggregation defaults to mean and lower values are best unless max
+        #[arg(
+            long = "attribute",
+            value_name = "SUMMARY",
+            value_parser = evaluation::latex::Summary::parse
+        )]
+        summaries: Vec<evaluation::latex::Summary>,
+
+        /// Only summarizes problems solved by all solvers
+        #[arg(long, default_value = "false")]
+        common: bool,
+
+        /// File to write the tables to, instead of stdout
+        #[arg(short, long)]
+        out: Option<PathBuf>,
+    },
+    /// Merges the learn.csv and solve.csv of results dirs into one, with the
+    /// union of their columns. Other files, such as output/*.csv and exports,
+    /// are not merged
+    Merge {
+        /// The results dirs to merge
+        #[arg(required = true)]
+        results: Vec<PathBuf>,
+
+        /// Specifies which directory merged results will be written to
+        #[arg(short, long, required = false, default_value = "results")]
+        out: PathBuf,
+
+        /// Columns in which duplicates of a run must agree, others are kept
+        /// from the first duplicate and duplicates that differ are marked as
+        /// conflicting
+        #[arg(long, value_delimiter = ',', default_value = "exit_code,outcome")]
+        conflict_columns: Vec<String>,
+    },
+    /// Writes cactus plots and coverage over time per solver as csv and svg
+    Plot {
+        /// The results dir, or its solve.csv
+        results: PathBuf,
+
+        /// Attribute giving the time of a run
+        #[arg(long, default_value = "run_time")]
+        time: String,
+
+        /// Whether to use a logarithmic time axis
+        #[arg(long, default_value = "false")]
+        log: bool,
+
+        /// Dir to write the plots to, instead of the results dir
+        #[arg(short, long)]
+        out: Option<PathBuf>,
+    },
 }
 
 fn main() -> Result<()> {
     logging::init();
     trace!("Reading args");
     let args = Args::parse();
+    match &args.command {
+        Some(Command::Collect {
+            work_dir,
+            out,
+            export,
+            provenance,
+        }) => return collect(work_dir, out, export, *provenance),
+        Some(Command::Compare {
+            results,
+            a,
+            b,
+            attributes,
+            maximize,
+        }) => {
+            print!(
+                "{}",
+                evaluation::compare::compare(results, a, b, attributes, *maximize)?
+            );
+            return Ok(());
+        }
+        Some(Command::Latex {
+            results,
+            summaries,
+            common,
+            out,
+        }) => {
+            let tex = evaluation::latex::latex(results, summaries, *common)?;
+            match out {
+                Some(out) => fs::write(out, tex)?,
+                None => print!("{}", tex),
+            }
+            return Ok(());
+        }
+        Some(Command::Merge {
+            results,
+            out,
+            conflict_columns,
+        }) => {
+            return evaluation::merge::merge(results, out, conflict_columns);
+        }
+        Some(Command::Plot {
+            results,
+            time,
+            log,
+            out,
+        }) => {
+            let out_dir = match (out, results.is_dir()) {
+                (Some(out), _) => out.to_owned(),
+                (None, true) => results.to_owned(),
+                (None, false) => results
+                    .parent()
+                    .map(|p| p.to_path_buf())
+                    .unwrap_or_default(),
+            };
+            return evaluation::plot::plot(results, time, *log, &out_dir);
+        }
+        None => {}
+    }
+    ctrlc::set_handler(|| {
+        // A second interrupt does not wait for runs to be stopped
+        if execution::interrupted() {
+            std::process::exit(130);
+        }
+        execution::interrupt();
+    })?;
+    if let Some(path) = &args.events {
+        events::init(path)?;
+    }
     let out_dir = args.out.absolutize()?.to_path_buf();
-    match &args.prior_run {
-        Some(path) => _main(&args, &path, &out_dir),
+    let suite_path = args
+        .suite
+        .as_ref()
+        .expect("suite is required")
+        .absolutize()?
+        .to_path_buf();
+    let result = match &args.prior_run {
+        Some(path) => {
+            let path = path.absolutize()?.to_path_buf();
+            let result = _main(&args, &path, &suite_path, &out_dir);
+            if is_interrupted(&result) {
+                print_continuation(&path, &suite_path);
+            }
+            result
+        }
         None => {
             trace!("Creating work dir");
-            fs::create_dir_all(&args.work_dir)?;
-            let temp_dir: tempfile::TempDir = tempdir_in(&args.work_dir)?;
-            let result = _main(&args, &temp_dir.path().to_path_buf(), &out_dir);
-            if args.keep_working_dir {
+            let work_dir = args.work_dir.absolutize()?.to_path_buf();
+            fs::create_dir_all(&work_dir)?;
+            let temp_dir: tempfile::TempDir = tempdir_in(&work_dir)?;
+            let result = _main(&args, temp_dir.path(), &suite_path, &out_dir);
+            let html = args.export.contains(&evaluation::export::Format::Html);
+            if args.keep_working_dir || html || args.detach || is_interrupted(&result) {
                 trace!("Releasing temp dir");
-                let _ = temp_dir.into_path();
+                let path = temp_dir.into_path();
+                if html && !args.keep_working_dir {
+                    info!("Kept work dir {:?} for the html report", path);
+                }
+                if is_interrupted(&result) {
+                    print_continuation(&path, &suite_path);
+                }
             }
             result
         }
+    };
+    // Exits like a shell does for a process stopped by SIGINT
+    if is_interrupted(&result) {
+        std::process::exit(130);
+    }
+    result
+}
+
+fn is_interrupted(result: &Result<()>) -> bool {
+    match result {
+        Ok(_) => false,
+        Err(err) => err.is::<execution::Interrupted>(),
     }
 }
 
-fn _main(args: &Args, temp_dir: &PathBuf, out_dir: &PathBuf) -> Result<()> {
+fn print_continuation(work_dir: &Path, suite_path: &Path) {
+    info!(
+        "Continue with: labyr --prior-run {} {}",
+        work_dir.to_string_lossy(),
+        suite_path.to_string_lossy()
+    );
+}
+
+fn _main(args: &Args, temp_dir: &Path, suite_path: &PathBuf, out_dir: &PathBuf) -> Result<()> {
     trace!("Determining number of threads");
     let threads = match args.threads {
         0 => available_parallelism()?.get(),
         _ => args.threads,
     };
     info!("Thread count: {}", threads);
-    let suite_path = args.suite.absolutize()?.to_path_buf();
+    let history = args
+        .history
+        .iter()
+        .map(|p| Ok(p.absolutize()?.to_path_buf()))
+        .collect::<Result<Vec<PathBuf>>>()?;
     trace!("Generating instance");
-    let instance = setup::run(&temp_dir, &suite_path, args.force_learn, args.force_solve)?;
+    let instance = setup::run(temp_dir, suite_path, args.force_learn, args.force_solve)?;
     trace!("Executing instance");
-    execution::execute(instance.to_owned(), args.execution_kind, threads)?;
-    evaluation::eval(&out_dir, &instance)?;
+    execution::execute(
+        instance.to_owned(),
+        args.execution_kind,
+        &execution::Options {
+            threads,
+            max_memory: args.max_memory,
+            cpus: match &args.cpus {
+                Some(cpus) => Some(execution::pinning::parse_cpu_list(cpus)?),
+                None => None,
+            },
+            cpus_per_run: args.cpus_per_run,
+            skip_siblings: args.skip_siblings,
+            detach: args.detach,
+            history,
+            progress_per_runner: args.progress_per_runner,
+        },
+    )?;
+    if args.detach {
+        fs::write(
+            temp_dir.join(SUITE_FILE),
+            suite_path.to_string_lossy().as_bytes(),
+        )?;
+        fs::write(temp_dir.join(SUITE_SOURCE_FILE), &instance.suite_source)?;
+        info!(
+            "Collect results with: labyr collect {}",
+            temp_dir.to_string_lossy()
+        );
+        return Ok(());
+    }
+    evaluation::eval(out_dir, &instance, &args.export, args.provenance)?;
+    Ok(())
+}
+
+fn collect(
+    work_dir: &PathBuf,
+    out: &PathBuf,
+    export: &[evaluation::export::Format],
+    provenance: bool,
+) -> Result<()> {
+    let work_dir = work_dir.absolutize()?.to_path_buf();
+    let out_dir = out.absolutize()?.to_path_buf();
+    if !execution::slurm::collect(&work_dir)? {
+        bail!("Jobs of {:?} are not done", work_dir);
+    }
+    let suite_path = PathBuf::from(fs::read_to_string(work_dir.join(SUITE_FILE))?.trim());
+    let suite_content = fs::read_to_string(work_dir.join(SUITE_SOURCE_FILE))?;
+    trace!("Rebuilding instance");
+    let instance = setup::load(&work_dir, &suite_path, &suite_content)?;
+    evaluation::eval(&out_dir, &instance, export, provenance)?;
     Ok(())
 }
@@ -0,0 +1,82 @@
+use anyhow::Result;
+use log::warn;
+use once_cell::sync::OnceCell;
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::fs::{File, OpenOptions};
+use std::io::Write;
+use std::path::PathBuf;
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+static SINK: OnceCell<Mutex<File>> = OnceCell::new();
+
+/// Lifecycle event of an experiment, written as a line of JSON
+#[derive(Serialize, Debug)]
+#[serde(tag = "event", rename_all = "kebab-case")]
+pub enum Event<'a> {
+    StageStarted {
+        stage: &'a str,
+    },
+    RunQueued {
+        run: usize,
+        name: &'a str,
+    },
+    RunStarted {
+        run: usize,
+        name: &'a str,
+    },
+    RunFinished {
+        run: usize,
+        name: &'a str,
+        outcome: String,
+        exit_code: Option<i32>,
+        metrics: &'a BTreeMap<String, String>,
+    },
+    ExperimentFinished {
+        interrupted: bool,
+        /// Number of runs by outcome
+        outcomes: &'a BTreeMap<String, usize>,
+    },
+}
+
+#[derive(Serialize)]
+struct Record<'a> {
+    /// Seconds since the unix epoch
+    time: f64,
+    #[serde(flatten)]
+    event: &'a Event<'a>,
+}
+
+/// Appends events to the file at path, which may be a FIFO, in which case
+/// this blocks until it has a reader
+pub fn init(path: &PathBuf) -> Result<()> {
+    let file = OpenOptions::new().create(true).append(true).open(path)?;
+    let _ = SINK.set(Mutex::new(file));
+    Ok(())
+}
+
+pub fn enabled() -> bool {
+    SINK.get().is_some()
+}
+
+pub fn emit(event: &Event) {
+    let Some(sink) = SINK.get() else {
+        return;
+    };
+    let time = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs_f64())
+        .unwrap_or_default();
+    let line = match serde_json::to_string(&Record { time, event }) {
+        Ok(line) => line,
+        Err(e) => {
+            warn!("Could not serialize event {:?}: {}", event, e);
+            return;
+        }
+    };
+    let mut file = sink.lock().unwrap();
+    if let Err(e) = writeln!(file, "{}", line).and_then(|_| file.flush()) {
+        warn!("Could not write event: {}", e);
+    }
+}
@@ -27,21 +27,29 @@ pub struct ProgressBar {
     pg: indicatif::ProgressBar,
 }
 
+/// Width of the terminal progress bars are drawn to
+pub fn width() -> usize {
+    console::Term::stderr().size().1 as usize
+}
+
 impl ProgressBar {
-    pub fn new(len: usize) -> Self {
+    /// Width taken by a bar of len with prefix, before its message
+    pub fn width(len: usize, prefix: &str) -> usize {
+        prefix.len() + 30 + 2 * len.to_string().len()
+    }
+    pub fn new(len: usize, prefix: &str) -> Self {
         let pg = indicatif::ProgressBar::new(len as u64);
         pg.set_style(
-            ProgressStyle::with_template(
-                "[{bar:24}] {pos}/{len} {wide_msg}",
-            )
-            .unwrap()
-            .progress_chars("=> "),
+            ProgressStyle::with_template("{prefix} [{bar:24}] {pos}/{len} {wide_msg}")
+                .unwrap()
+                .progress_chars("=> "),
         );
+        pg.set_prefix(prefix.to_owned());
         let pg = progresser().add(pg);
         Self { pg }
     }
-    pub fn inc(&self) {
-        self.pg.inc(1);
+    pub fn set(&self, pos: usize) {
+        self.pg.set_position(pos as u64);
     }
     pub fn msg(&self, msg: String) {
         self.pg.set_message(msg)
@@ -0,0 +1,36 @@
+use anyhow::Result;
+use std::collections::BTreeMap;
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::Path;
+
+/// File in a run dir holding measurements of the run as key=value lines
+pub const METRICS_FILE: &str = "metrics";
+
+/// Metric holding the wall time of a run in seconds, of its final attempt if
+/// it is retried
+pub const RUN_TIME: &str = "run_time";
+/// Metric holding the wall time of a retried run in seconds, including all
+/// attempts
+pub const TOTAL_RUN_TIME: &str = "total_run_time";
+
+/// Appends a metric to the metrics of the run in dir
+pub fn record(dir: &Path, key: &str, value: &str) -> Result<()> {
+    let mut file = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(dir.join(METRICS_FILE))?;
+    writeln!(file, "{}={}", key, value)?;
+    Ok(())
+}
+
+/// Reads the metrics of the run in dir, where later values of a key take
+/// precedence
+pub fn read(dir: &Path) -> BTreeMap<String, String> {
+    fs::read_to_string(dir.join(METRICS_FILE))
+        .unwrap_or_default()
+        .lines()
+        .filter_map(|l| l.split_once('='))
+        .map(|(k, v)| (k.trim().to_owned(), v.trim().to_owned()))
+        .collect()
+}
@@ -1,14 +1,38 @@
-use super::suite::{Attribute, RunnerKind, Suite};
-use anyhow::Result;
+use super::suite::{Attribute, Retry, RunnerKind, SlurmOptions, Suite};
+use super::template;
+use crate::misc::metrics;
+use anyhow::{anyhow, bail, Result};
 use log::trace;
-use std::{env, fs, path::PathBuf, process::Command};
+use path_absolutize::Absolutize;
+use std::{
+    collections::HashMap,
+    env, fs,
+    path::{Path, PathBuf},
+    process::Command,
+};
+
+/// File marking a run that was stopped by an interrupt before finishing
+pub const INTERRUPTED_FILE: &str = "interrupted";
+
+/// File marking a run that was never started as its solver gave up on the task
+pub const SKIPPED_FILE: &str = "skipped";
+
+/// File in the work dir naming the runner, task and problem of each run dir
+pub const MANIFEST_FILE: &str = "runs.csv";
 
 #[derive(Debug, Clone)]
 pub struct Instance {
+    /// Content of the suite file the instance was generated from
+    pub suite_source: String,
+    pub work_dir: PathBuf,
     pub learn_dir: PathBuf,
     pub solve_dir: PathBuf,
     pub learn_mem_limit: Option<usize>,
     pub solve_mem_limit: Option<usize>,
+    pub learn_time_limit: Option<usize>,
+    pub solve_time_limit: Option<usize>,
+    pub learn_slurm: SlurmOptions,
+    pub solve_slurm: SlurmOptions,
     pub runners: Vec<Runner>,
     pub tasks: Vec<Task>,
     pub attributes: Vec<Attribute>,
@@ -20,6 +44,8 @@ pub struct Runner {
     pub name: String,
     pub attribute: Option<usize>,
     pub kind: RunnerKind,
+    pub give_up_after: Option<usize>,
+    pub give_up_on: Vec<i32>,
 }
 
 #[derive(Debug, Clone)]
@@ -27,6 +53,7 @@ pub struct Task {
     pub name: String,
     pub learn: Vec<String>,
     pub solve: Vec<String>,
+    pub give_up_after: Option<usize>,
 }
 
 #[derive(Debug, Clone, PartialEq, Eq, Hash)]
@@ -48,8 +75,78 @@ pub struct Run {
     pub skip: bool,
 }
 
-pub fn generate(suite: Suite, force_learn: bool, force_solve: bool) -> Result<Instance> {
-    let working_dir = env::current_dir()?;
+impl Instance {
+    /// Memory limit in MB of run
+    pub fn memory_limit(&self, run_index: usize) -> Option<usize> {
+        match self.runs[run_index].kind {
+            RunKind::Learner => self.learn_mem_limit,
+            RunKind::Solver { .. } => self.solve_mem_limit,
+        }
+    }
+
+    /// Time limit in seconds of run
+    pub fn time_limit(&self, run_index: usize) -> Option<usize> {
+        match self.runs[run_index].kind {
+            RunKind::Learner => self.learn_time_limit,
+            RunKind::Solver { .. } => self.solve_time_limit,
+        }
+    }
+
+    /// Number of consecutive failures of a solver after which the remaining
+    /// problems of the task are skipped
+    pub fn give_up_after(&self, run_index: usize) -> Option<usize> {
+        let run = &self.runs[run_index];
+        match run.kind {
+            RunKind::Learner => None,
+            RunKind::Solver { .. } => self.runners[run.runner_index]
+                .give_up_after
+                .or(self.tasks[run.task_index].give_up_after),
+        }
+    }
+
+    /// Exit codes of a solver counting towards giving up on a task
+    pub fn give_up_on(&self, run_index: usize) -> &[i32] {
+        &self.runners[self.runs[run_index].runner_index].give_up_on
+    }
+
+    /// Names of the runner, task and, for solvers, problem of run
+    pub fn run_key(&self, run_index: usize) -> (&str, &str, Option<&str>) {
+        let run = &self.runs[run_index];
+        let task = &self.tasks[run.task_index];
+        (
+            &self.runners[run.runner_index].name,
+            &task.name,
+            match run.kind {
+                RunKind::Learner => None,
+                RunKind::Solver { problem_index, .. } => Some(&task.solve[problem_index]),
+            },
+        )
+    }
+}
+
+/// Settings of generating an instance
+#[derive(Debug, Clone, Default)]
+pub struct Options {
+    /// Content of the suite file the suite was parsed from, if any
+    pub suite_source: String,
+    /// Whether to rerun learners that already have an exit code
+    pub force_learn: bool,
+    /// Whether to rerun solvers that already have an exit code
+    pub force_solve: bool,
+    /// Whether to rebuild the instance of an earlier execution without
+    /// writing scripts, such that it is left untouched
+    pub rebuild: bool,
+}
+
+/// Generates the runs of suite in working dir
+/// Paths of the suite that are not resolved yet are taken relative to the
+/// current dir, and runs that already have an exit code are marked as skip,
+/// unless forced
+pub fn generate(working_dir: &Path, mut suite: Suite, options: &Options) -> Result<Instance> {
+    suite.resolve(&env::current_dir()?)?;
+    suite.validate()?;
+    // Runners are executed from their run dir
+    let working_dir = working_dir.absolutize()?.to_path_buf();
     let learn_dir = working_dir.join("learn");
     let solve_dir = working_dir.join("solve");
     let mut runs: Vec<Run> = vec![];
@@ -63,24 +160,43 @@ pub fn generate(suite: Suite, force_learn: bool, force_solve: bool) -> Result<In
             .filter(|(_, r)| r.kind == RunnerKind::Learn)
         {
             let dir = learn_dir.join(format!("{}", i));
-            let mut args = learner.args.clone();
-            args.push(task.name.to_owned());
-            args.push(task.domain.to_string_lossy().to_string());
-            for problem in task.learn.iter() {
-                args.push(problem.to_string_lossy().to_string());
-            }
-            let (exe, skip) = match dir.join("exit_code").exists() {
-                true => (dir.join("runner.sh"), true),
-                false => (
-                    generate_script(
-                        &dir,
-                        &learner.path,
-                        &args,
-                        suite.time_limit_learn,
-                        suite.memory_limit_learn,
-                    )?,
-                    false,
-                ),
+            let problems: Vec<String> = task
+                .learn
+                .iter()
+                .map(|p| p.to_string_lossy().to_string())
+                .collect();
+            let args = match template::is_templated(&learner.args) {
+                true => template::expand(
+                    &learner.args,
+                    &HashMap::from([
+                        ("out", vec!["out".to_owned()]),
+                        ("dir", vec![dir.to_string_lossy().to_string()]),
+                        ("task", vec![task.name.to_owned()]),
+                        ("domain", vec![task.domain.to_string_lossy().to_string()]),
+                        ("problems", problems),
+                    ]),
+                )?,
+                false => {
+                    let mut args = vec!["out".to_owned()];
+                    args.extend(learner.args.iter().cloned());
+                    args.push(task.name.to_owned());
+                    args.push(task.domain.to_string_lossy().to_string());
+                    args.extend(problems);
+                    args
+                }
+            };
+            let skip = dir.join("exit_code").exists();
+            let exe = match skip || options.rebuild {
+                true => dir.join("runner.sh"),
+                false => generate_script(
+                    &dir,
+                    learner.launcher.as_ref().unwrap_or(&suite.launcher),
+                    &learner.path,
+                    &args,
+                    suite.time_limit_learn,
+                    suite.memory_limit_learn,
+                    learner.retry.as_ref(),
+                )?,
             };
             runs.push(Run {
                 dir,
@@ -88,7 +204,7 @@ pub fn generate(suite: Suite, force_learn: bool, force_solve: bool) -> Result<In
                 runner_index: learner_index,
                 task_index,
                 kind: RunKind::Learner,
-                skip: skip && !force_learn,
+                skip: skip && !options.force_learn,
             });
             i += 1;
         }
@@ -103,36 +219,60 @@ pub fn generate(suite: Suite, force_learn: bool, force_solve: bool) -> Result<In
                 .filter(|(_, r)| r.kind != RunnerKind::Learn)
             {
                 let dir = solve_dir.join(format!("{}", i));
-                let mut args = solver.args.clone();
-                let depends = match &solver.depends {
-                    Some(depends) => Some(
+                let depends = solver
+                    .depends
+                    .as_ref()
+                    .map(|depends| {
                         runs.iter()
                             .position(|l| {
                                 task_index == l.task_index
                                     && l.kind == RunKind::Learner
                                     && depends == &suite.runners[l.runner_index].name
                             })
-                            .unwrap(),
-                    ),
-                    None => None,
+                            .ok_or_else(|| {
+                                anyhow!(
+                                    "Runner {} depends on {}, which has no run of task {}",
+                                    solver.name,
+                                    depends,
+                                    task.name
+                                )
+                            })
+                    })
+                    .transpose()?;
+                let depends_dir = depends.map(|d| runs[d].dir.to_string_lossy().to_string());
+                let args = match template::is_templated(&solver.args) {
+                    true => template::expand(
+                        &solver.args,
+                        &HashMap::from([
+                            ("out", vec!["out".to_owned()]),
+                            ("dir", vec![dir.to_string_lossy().to_string()]),
+                            ("task", vec![task.name.to_owned()]),
+                            ("domain", vec![task.domain.to_string_lossy().to_string()]),
+                            ("problem", vec![problem.to_string_lossy().to_string()]),
+                            ("depends.dir", depends_dir.into_iter().collect()),
+                        ]),
+                    )?,
+                    false => {
+                        let mut args = vec!["out".to_owned()];
+                        args.extend(solver.args.iter().cloned());
+                        args.extend(depends_dir);
+                        args.push(task.domain.to_string_lossy().to_string());
+                        args.push(problem.to_string_lossy().to_string());
+                        args
+                    }
                 };
-                if let Some(depends) = depends {
-                    args.push(runs[depends].dir.to_string_lossy().to_string());
-                }
-                args.push(task.domain.to_string_lossy().to_string());
-                args.push(problem.to_string_lossy().to_string());
-                let (exe, skip) = match dir.join("exit_code").exists() {
-                    true => (dir.join("runner.sh"), true),
-                    false => (
-                        generate_script(
-                            &dir,
-                            &solver.path,
-                            &args,
-                            suite.time_limit_solve,
-                            suite.memory_limit_solve,
-                        )?,
-                        false,
-                    ),
+                let skip = dir.join("exit_code").exists();
+                let exe = match skip || options.rebuild {
+                    true => dir.join("runner.sh"),
+                    false => generate_script(
+                        &dir,
+                        solver.launcher.as_ref().unwrap_or(&suite.launcher),
+                        &solver.path,
+                        &args,
+                        suite.time_limit_solve,
+                        suite.memory_limit_solve,
+                        solver.retry.as_ref(),
+                    )?,
                 };
                 runs.push(Run {
                     dir,
@@ -144,7 +284,7 @@ pub fn generate(suite: Suite, force_learn: bool, force_solve: bool) -> Result<In
                         depends,
                     },
                     skip: skip
-                        && !force_solve
+                        && !options.force_solve
                         && match depends {
                             Some(d) => runs[d].skip,
                             None => true,
@@ -156,18 +296,23 @@ pub fn generate(suite: Suite, force_learn: bool, force_solve: bool) -> Result<In
         }
     }
     let attributes = suite.attributes;
-    let runners = suite
-        .runners
-        .into_iter()
-        .map(|r| Runner {
-            name: r.name,
-            attribute: match r.attribute {
-                Some(a) => Some(attributes.iter().position(|p| p.name == a).unwrap()),
-                None => None,
+    let mut runners = vec![];
+    for r in suite.runners.into_iter() {
+        let attribute = match &r.attribute {
+            Some(a) => match attributes.iter().position(|p| &p.name == a) {
+                Some(index) => Some(index),
+                None => bail!("Runner {} uses undefined attribute {}", r.name, a),
             },
+            None => None,
+        };
+        runners.push(Runner {
+            name: r.name,
+            attribute,
             kind: r.kind,
-        })
-        .collect();
+            give_up_after: r.give_up_after,
+            give_up_on: r.give_up_on,
+        });
+    }
     let mut tasks = vec![];
     for task in suite.tasks.into_iter() {
         let name = task.name;
@@ -177,7 +322,7 @@ pub fn generate(suite: Suite, force_learn: bool, force_solve: bool) -> Result<In
         for p in task.learn.into_iter() {
             learn.push(
                 p.file_stem()
-                    .expect(&format!("problem {:?} has no name", p))
+                    .ok_or_else(|| anyhow!("Problem {:?} has no name", p))?
                     .to_string_lossy()
                     .to_string(),
             );
@@ -186,34 +331,80 @@ pub fn generate(suite: Suite, force_learn: bool, force_solve: bool) -> Result<In
         for p in task.solve.into_iter() {
             solve.push(
                 p.file_stem()
-                    .expect(&format!("problem {:?} has no name", p))
+                    .ok_or_else(|| anyhow!("Problem {:?} has no name", p))?
                     .to_string_lossy()
                     .to_string(),
             );
         }
 
-        tasks.push(Task { name, learn, solve })
+        tasks.push(Task {
+            name,
+            learn,
+            solve,
+            give_up_after: task.give_up_after,
+        })
     }
-    Ok(Instance {
+    let instance = Instance {
+        suite_source: options.suite_source.to_owned(),
+        work_dir: working_dir.to_owned(),
         learn_dir,
         solve_dir,
         learn_mem_limit: suite.memory_limit_learn,
         solve_mem_limit: suite.memory_limit_solve,
+        learn_time_limit: suite.time_limit_learn,
+        solve_time_limit: suite.time_limit_solve,
+        learn_slurm: suite.slurm.learn_options(),
+        solve_slurm: suite.slurm.solve_options(),
         runners,
         tasks,
         attributes,
         runs,
-    })
+    };
+    if !options.rebuild {
+        write_manifest(&instance)?;
+    }
+    Ok(instance)
+}
+
+/// Writes the manifest of instance, such that its runs can be identified by
+/// later instances
+fn write_manifest(instance: &Instance) -> Result<()> {
+    let mut writer = csv::Writer::from_path(instance.work_dir.join(MANIFEST_FILE))?;
+    writer.write_record(["dir", "runner", "task", "problem"])?;
+    for (i, run) in instance.runs.iter().enumerate() {
+        let (runner, task, problem) = instance.run_key(i);
+        let dir = run.dir.strip_prefix(&instance.work_dir).unwrap_or(&run.dir);
+        writer.write_record([
+            dir.to_string_lossy().as_ref(),
+            runner,
+            task,
+            problem.unwrap_or_default(),
+        ])?;
+    }
+    writer.flush()?;
+    Ok(())
 }
 
 fn generate_script(
     dir: &PathBuf,
-    exe: &PathBuf,
-    args: &Vec<String>,
+    launcher: &[String],
+    exe: &Path,
+    args: &[String],
     time_limit: Option<usize>,
     memory_limit: Option<usize>,
+    retry: Option<&Retry>,
 ) -> Result<PathBuf> {
-    fs::create_dir_all(&dir)?;
+    fs::create_dir_all(dir)?;
+    // Metrics of an earlier unfinished run are stale
+    let _ = fs::remove_file(dir.join(metrics::METRICS_FILE));
+    let _ = fs::remove_file(dir.join(INTERRUPTED_FILE));
+    let _ = fs::remove_file(dir.join(SKIPPED_FILE));
+    // As are the logs of its earlier attempts
+    for entry in fs::read_dir(dir)?.flatten() {
+        if entry.file_name().to_string_lossy().starts_with("log.") {
+            let _ = fs::remove_file(entry.path());
+        }
+    }
     let mut content = "#!/bin/bash\n".to_owned();
     if let Some(mem) = memory_limit {
         content.push_str(&format!("ulimit -v {}\n", mem * 1000));
@@ -222,15 +413,83 @@ fn generate_script(
     if let Some(time) = time_limit {
         command.push_str(&format!("timeout {}s ", time));
     }
-    command.push_str(&format!(
-        "{} out{}",
-        exe.to_string_lossy(),
-        args.iter()
-            .map(|arg| format!(" {}", arg))
-            .collect::<String>()
-    ));
-    content.push_str(&format!("$(eval \"{}\"&>log)\n", command));
-    content.push_str(&format!("echo $? > exit_code"));
+    for arg in launcher.iter() {
+        command.push_str(&format!("{} ", quote(arg)));
+    }
+    command.push_str(&quote(&exe.to_string_lossy()));
+    for arg in args.iter() {
+        command.push_str(&format!(" {}", quote(arg)));
+    }
+    fs::write(dir.join("command"), &command)?;
+    match retry {
+        None => {
+            content.push_str("START=$(date +%s%N)\n");
+            content.push_str(&format!("{} &>log\n", command));
+            content.push_str("CODE=$?\n");
+        }
+        Some(retry) => {
+            let mut transient = vec![];
+            if !retry.exit_codes.is_empty() {
+                transient.push(format!(
+                    "[[ \" {} \" == *\" $CODE \"* ]]",
+                    retry
+                        .exit_codes
+                        .iter()
+                        .map(|c| c.to_string())
+                        .collect::<Vec<String>>()
+                        .join(" ")
+                ));
+            }
+            if !retry.patterns.is_empty() {
+                transient.push(format!(
+                    "grep -Eq{} log",
+                    retry
+                        .patterns
+                        .iter()
+                        .map(|p| format!(" -e {}", quote(p)))
+                        .collect::<String>()
+                ));
+            }
+            if transient.is_empty() {
+                transient.push("false".to_owned());
+            }
+            content.push_str("FIRST=$(date +%s%N)\n");
+            content.push_str(&format!("for ATTEMPT in $(seq 1 {}); do\n", retry.attempts));
+            content.push_str("    START=$(date +%s%N)\n");
+            content.push_str(&format!("    {} &>log\n", command));
+            content.push_str("    CODE=$?\n");
+            content.push_str(&format!(
+                "    if [ $ATTEMPT -lt {} ] && {{ {}; }}; then\n",
+                retry.attempts,
+                transient.join(" || ")
+            ));
+            content.push_str("        mv log log.$ATTEMPT\n");
+            content.push_str("        continue\n");
+            content.push_str("    fi\n");
+            content.push_str("    break\n");
+            content.push_str("done\n");
+            content.push_str(&format!(
+                "echo \"attempts=$ATTEMPT\" >> {}\n",
+                metrics::METRICS_FILE
+            ));
+        }
+    }
+    content.push_str("END=$(date +%s%N)\n");
+    // The run time is that of the final attempt, such that it is comparable
+    // to runs that were not retried
+    let mut times = vec![(metrics::RUN_TIME, "START")];
+    if retry.is_some() {
+        times.push((metrics::TOTAL_RUN_TIME, "FIRST"));
+    }
+    for (metric, start) in times {
+        content.push_str(&format!("MS=$(((END - {}) / 1000000))\n", start));
+        content.push_str(&format!(
+            "printf \"{}=%d.%03d\\n\" $((MS / 1000)) $((MS % 1000)) >> {}\n",
+            metric,
+            metrics::METRICS_FILE
+        ));
+    }
+    content.push_str("echo $CODE > exit_code");
     let runner_path = dir.join("runner.sh");
     fs::write(&runner_path, content)?;
     let mut cmd = Command::new("chmod");
@@ -239,3 +498,107 @@ fn generate_script(
     cmd.status()?;
     Ok(runner_path)
 }
+
+/// Argument quoted for the shell, such that it is passed on as is
+fn quote(arg: &str) -> String {
+    let safe = |c: char| c.is_ascii_alphanumeric() || "_-./:=,+@%".contains(c);
+    match !arg.is_empty() && arg.chars().all(safe) {
+        true => arg.to_owned(),
+        false => format!("'{}'", arg.replace('\'', "'\\''")),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn quote_leaves_plain_args() {
+        assert_eq!(quote("--search"), "--search");
+        assert_eq!(quote("/tmp/domain.pddl"), "/tmp/domain.pddl");
+    }
+
+    #[test]
+    fn quote_escapes_special_args() {
+        assert_eq!(quote("astar(lmcut())"), "'astar(lmcut())'");
+        assert_eq!(quote("a b"), "'a b'");
+        assert_eq!(quote("it's"), "'it'\\''s'");
+        assert_eq!(quote(""), "''");
+    }
+
+    #[test]
+    fn script_passes_args_as_is() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let run_dir = dir.path().join("run");
+        let args = vec![
+            "astar(lmcut())".to_owned(),
+            "a b".to_owned(),
+            "it's $HOME".to_owned(),
+        ];
+        let script = generate_script(
+            &run_dir,
+            &[],
+            &PathBuf::from("printf"),
+            &[vec!["%s\\n".to_owned()], args.clone()].concat(),
+            None,
+            None,
+            None,
+        )?;
+        let status = Command::new(&script).current_dir(&run_dir).status()?;
+        assert!(status.success());
+        assert_eq!(fs::read_to_string(run_dir.join("exit_code"))?.trim(), "0");
+        let log = fs::read_to_string(run_dir.join("log"))?;
+        assert_eq!(log.lines().collect::<Vec<_>>(), args);
+        Ok(())
+    }
+
+    #[test]
+    fn script_times_final_attempt_of_retried_runs() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let run_dir = dir.path().join("run");
+        // Fails transiently once, taking longer than it then takes to succeed
+        let script = generate_script(
+            &run_dir,
+            &[],
+            &PathBuf::from("bash"),
+            &[
+                "-c".to_owned(),
+                "[ -e marker ] && exit 0; touch marker; sleep 0.3; exit 75".to_owned(),
+            ],
+            None,
+            None,
+            Some(&Retry {
+                attempts: 3,
+                exit_codes: vec![75],
+                patterns: vec![],
+            }),
+        )?;
+        Command::new(&script).current_dir(&run_dir).status()?;
+        assert_eq!(fs::read_to_string(run_dir.join("exit_code"))?.trim(), "0");
+        let metrics = metrics::read(&run_dir);
+        assert_eq!(metrics["attempts"], "2");
+        let time = |metric: &str| metrics[metric].parse::<f64>().unwrap();
+        assert!(time(metrics::RUN_TIME) < 0.3);
+        assert!(time(metrics::TOTAL_RUN_TIME) >= 0.3);
+        Ok(())
+    }
+
+    #[test]
+    fn script_passes_launcher_as_is() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let run_dir = dir.path().join("run");
+        let script = generate_script(
+            &run_dir,
+            &["env".to_owned(), "LABYR_TEST=a b;c".to_owned()],
+            &PathBuf::from("printenv"),
+            &["LABYR_TEST".to_owned()],
+            None,
+            None,
+            None,
+        )?;
+        let status = Command::new(&script).current_dir(&run_dir).status()?;
+        assert!(status.success());
+        assert_eq!(fs::read_to_string(run_dir.join("log"))?, "a b;c\n");
+        Ok(())
+    }
+}
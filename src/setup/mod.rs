@@ -1,25 +1,58 @@
 pub mod instance;
 pub mod suite;
+pub mod template;
 
 use crate::setup::instance::Instance;
-use anyhow::Result;
+use anyhow::{anyhow, Result};
 use log::trace;
-use std::{env, fs, path::PathBuf};
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
 
 pub fn run(
-    temp_dir: &PathBuf,
+    temp_dir: &Path,
     suite_path: &PathBuf,
     force_learn: bool,
     force_solve: bool,
 ) -> Result<Instance> {
     trace!("Reading suite file");
     let suite_content = fs::read_to_string(suite_path)?;
-    trace!("Changing working directory to {:?} parent", suite_path);
-    env::set_current_dir(suite_path.parent().expect("suite is an orphan"))?;
-    trace!("Parsing suite file");
-    let suite = suite::parse(&suite_content)?;
-    trace!("Changing working directory to {:?}", temp_dir);
-    env::set_current_dir(temp_dir)?;
+    let suite = parse(suite_path, &suite_content)?;
     trace!("Generating instance");
-    instance::generate(suite, force_learn, force_solve)
+    instance::generate(
+        temp_dir,
+        suite,
+        &instance::Options {
+            suite_source: suite_content,
+            force_learn,
+            force_solve,
+            rebuild: false,
+        },
+    )
+}
+
+/// Rebuilds the instance of an earlier execution in work dir from the suite
+/// content it was generated from, without regenerating its scripts
+pub fn load(work_dir: &Path, suite_path: &PathBuf, suite_content: &str) -> Result<Instance> {
+    let suite = parse(suite_path, suite_content)?;
+    trace!("Rebuilding instance");
+    instance::generate(
+        work_dir,
+        suite,
+        &instance::Options {
+            suite_source: suite_content.to_owned(),
+            rebuild: true,
+            ..Default::default()
+        },
+    )
+}
+
+/// Parses suite content, whose paths are relative to the suite at suite path
+fn parse(suite_path: &PathBuf, suite_content: &str) -> Result<suite::Suite> {
+    trace!("Parsing suite file");
+    let base = suite_path
+        .parent()
+        .ok_or_else(|| anyhow!("Suite {:?} has no parent dir", suite_path))?;
+    suite::parse(suite_content, base)
 }
@@ -1,33 +1,95 @@
-use crate::misc::abs_path;
-use crate::misc::path_set;
 use crate::misc::regex_pattern;
-use anyhow::Result;
-use log::info;
+use crate::setup::template;
+use anyhow::{anyhow, bail, Result};
+use glob::glob;
+use log::{info, trace};
+use path_absolutize::Absolutize;
 use regex::Regex;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
 
-#[derive(serde::Deserialize)]
+#[derive(serde::Deserialize, Default)]
 pub struct Suite {
     pub time_limit_learn: Option<usize>,
     pub time_limit_solve: Option<usize>,
     pub memory_limit_learn: Option<usize>,
     pub memory_limit_solve: Option<usize>,
+    #[serde(default)]
+    pub launcher: Vec<String>,
+    #[serde(default)]
+    pub slurm: Slurm,
     pub runners: Vec<Runner>,
     #[serde(default)]
     pub attributes: Vec<Attribute>,
     pub tasks: Vec<Task>,
 }
 
+#[derive(serde::Deserialize, Default)]
+pub struct Slurm {
+    #[serde(flatten)]
+    pub options: SlurmOptions,
+    pub learn: Option<SlurmOptions>,
+    pub solve: Option<SlurmOptions>,
+}
+
+#[derive(serde::Deserialize, Debug, Clone, Default)]
+pub struct SlurmOptions {
+    pub job_name: Option<String>,
+    pub partition: Option<String>,
+    pub account: Option<String>,
+    pub qos: Option<String>,
+    pub cpus: Option<usize>,
+    pub time: Option<String>,
+    pub memory: Option<String>,
+    pub constraint: Option<String>,
+    #[serde(default)]
+    pub extra: Vec<String>,
+    pub max_array_size: Option<usize>,
+    pub throttle: Option<usize>,
+    pub runs_per_job: Option<usize>,
+}
+
 #[derive(serde::Deserialize)]
 pub struct Runner {
     pub name: String,
-    #[serde(with = "abs_path")]
+    /// Relative to the suite until resolved
     pub path: PathBuf,
     pub kind: RunnerKind,
     #[serde(default)]
     pub args: Vec<String>,
     pub depends: Option<String>,
     pub attribute: Option<String>,
+    pub launcher: Option<Vec<String>>,
+    pub retry: Option<Retry>,
+    /// Skips the remaining problems of a task after this many consecutive
+    /// failures, takes precedence over the setting of the task
+    pub give_up_after: Option<usize>,
+    /// Exit codes counting as failures towards giving up, by default 124 of
+    /// running out of time and 137 of being killed, such as by Slurm or a
+    /// cgroup on running out of memory
+    /// The memory limit of the suite is enforced with ulimit -v, on exceeding
+    /// which a runner exits with a code of its own, such as 22 for Fast
+    /// Downward, that has to be listed for memory outs to count
+    #[serde(default = "default_give_up_on")]
+    pub give_up_on: Vec<i32>,
+}
+
+fn default_give_up_on() -> Vec<i32> {
+    vec![124, 137]
+}
+
+/// Reruns a runner whose failure is deemed transient, either by its exit code
+/// or by its log matching an extended regex as understood by grep
+#[derive(serde::Deserialize, Debug, Clone)]
+pub struct Retry {
+    /// Maximum number of attempts, including the first
+    pub attempts: usize,
+    #[serde(default)]
+    pub exit_codes: Vec<i32>,
+    /// POSIX extended regexes as matched by grep -E on the node running the
+    /// runner, unlike the patterns of attributes, which are Rust regexes
+    #[serde(default)]
+    pub patterns: Vec<String>,
 }
 #[derive(serde::Deserialize, Debug, Clone, PartialEq, Eq)]
 pub enum RunnerKind {
@@ -52,12 +114,92 @@ pub struct Pattern {
 #[derive(serde::Deserialize)]
 pub struct Task {
     pub name: String,
-    #[serde(with = "abs_path")]
+    /// Relative to the suite until resolved
     pub domain: PathBuf,
-    #[serde(default, with = "path_set")]
+    /// Glob patterns relative to the suite until resolved to the paths they
+    /// match
+    #[serde(default)]
     pub learn: Vec<PathBuf>,
-    #[serde(default, with = "path_set")]
+    #[serde(default)]
     pub solve: Vec<PathBuf>,
+    /// Skips the remaining problems of a solver after this many consecutive
+    /// failures
+    pub give_up_after: Option<usize>,
+}
+
+impl Runner {
+    /// Runner of kind at path, with the defaults of a suite file otherwise
+    pub fn new(name: &str, path: &Path, kind: RunnerKind) -> Self {
+        Runner {
+            name: name.to_owned(),
+            path: path.to_owned(),
+            kind,
+            args: vec![],
+            depends: None,
+            attribute: None,
+            launcher: None,
+            retry: None,
+            give_up_after: None,
+            give_up_on: default_give_up_on(),
+        }
+    }
+}
+
+impl Task {
+    /// Task of domain without problems, which are given as glob patterns
+    pub fn new(name: &str, domain: &Path) -> Self {
+        Task {
+            name: name.to_owned(),
+            domain: domain.to_owned(),
+            learn: vec![],
+            solve: vec![],
+            give_up_after: None,
+        }
+    }
+}
+
+impl SlurmOptions {
+    /// Options given in other take precedence, extra flags are appended
+    pub fn merge(&self, other: &SlurmOptions) -> SlurmOptions {
+        SlurmOptions {
+            job_name: other.job_name.clone().or(self.job_name.clone()),
+            partition: other.partition.clone().or(self.partition.clone()),
+            account: other.account.clone().or(self.account.clone()),
+            qos: other.qos.clone().or(self.qos.clone()),
+            cpus: other.cpus.or(self.cpus),
+            time: other.time.clone().or(self.time.clone()),
+            memory: other.memory.clone().or(self.memory.clone()),
+            constraint: other.constraint.clone().or(self.constraint.clone()),
+            extra: self
+                .extra
+                .iter()
+                .chain(other.extra.iter())
+                .cloned()
+                .collect(),
+            max_array_size: other.max_array_size.or(self.max_array_size),
+            throttle: other.throttle.or(self.throttle),
+            runs_per_job: other.runs_per_job.or(self.runs_per_job),
+        }
+    }
+    /// Number of runs handled in sequence by each array element
+    pub fn runs_per_job(&self) -> usize {
+        self.runs_per_job.unwrap_or(1).max(1)
+    }
+}
+
+impl Slurm {
+    pub fn learn_options(&self) -> SlurmOptions {
+        match &self.learn {
+            Some(learn) => self.options.merge(learn),
+            None => self.options.clone(),
+        }
+    }
+    pub fn solve_options(&self) -> SlurmOptions {
+        match &self.solve {
+            Some(solve) => self.options.merge(solve),
+            None => self.options.clone(),
+        }
+    }
 }
 
 impl Suite {
@@ -97,52 +239,321 @@ impl Suite {
     }
 }
 
-pub fn parse(content: &str) -> Result<Suite> {
-    let suite: Suite = toml::from_str(content)?;
+/// Parses the suite content, resolving its paths relative to the base dir
+pub fn parse(content: &str, base: &Path) -> Result<Suite> {
+    let mut suite: Suite = toml::from_str(content)?;
+    suite.resolve(base)?;
+
+    info!("Runner count: {}", suite.runners.len());
+    info!("Runners: {:?}", suite.runner_names());
+    info!("Task count: {}", suite.tasks.len());
+    info!("Tasks: {:?}", suite.task_names());
+    info!("Problem learn count: {:?}", suite.total_problems_learn());
+    info!("Problem solve count: {:?}", suite.total_problems_solve());
+
+    Ok(suite)
+}
+
+impl Suite {
+    /// Makes the paths of runners and tasks absolute, expanding the glob
+    /// patterns of problems, relative to base
+    /// Resolved paths are kept as is, such that resolving again changes nothing
+    pub fn resolve(&mut self, base: &Path) -> Result<()> {
+        for runner in self.runners.iter_mut() {
+            runner.path = absolute(&runner.path, base)?;
+        }
+        for task in self.tasks.iter_mut() {
+            task.domain = absolute(&task.domain, base)?;
+            task.learn = expand(&task.learn, base)?;
+            task.solve = expand(&task.solve, base)?;
+        }
+        Ok(())
+    }
 
-    // Checking whether any runner dependency is undefined
-    for runner in suite.runners.iter() {
-        if let Some(depends) = &runner.depends {
-            if suite.get_runner(&depends).is_none() {
-                panic!(
-                    "Runner {} depends on undefined runner {}",
-                    runner.name, depends
-                );
+    /// Fails if the suite references undefined runners, attributes, or
+    /// placeholders, or if any task lacks problems
+    pub fn validate(&self) -> Result<()> {
+        // Checking whether any runner dependency is undefined
+        for runner in self.runners.iter() {
+            if let Some(depends) = &runner.depends {
+                match self.get_runner(depends) {
+                    None => bail!(
+                        "Runner {} depends on undefined runner {}",
+                        runner.name,
+                        depends
+                    ),
+                    Some(d) if d.kind != RunnerKind::Learn => bail!(
+                        "Runner {} depends on runner {}, which is no learner",
+                        runner.name,
+                        depends
+                    ),
+                    _ => {}
+                }
             }
         }
+
+        // Checking whether any attributes are undefined
+        for runner in self.runners.iter() {
+            if let Some(attribute) = &runner.attribute {
+                if self.get_attribute(attribute).is_none() {
+                    bail!(
+                        "Runner {} uses undefined attribute {}",
+                        runner.name,
+                        attribute
+                    );
+                }
+            }
+        }
+
+        // Checking whether any runner uses undefined placeholders
+        for runner in self.runners.iter() {
+            let allowed: &[&str] = match runner.kind {
+                RunnerKind::Learn => &template::LEARNER_PLACEHOLDERS,
+                RunnerKind::Solve => &template::SOLVER_PLACEHOLDERS,
+            };
+            for placeholder in template::placeholders(&runner.args) {
+                if !allowed.contains(&placeholder) {
+                    bail!(
+                        "Runner {} uses undefined placeholder {{{}}}",
+                        runner.name,
+                        placeholder
+                    );
+                }
+                if placeholder == "depends.dir" && runner.depends.is_none() {
+                    bail!(
+                        "Runner {} uses {{depends.dir}} but depends on no runner",
+                        runner.name
+                    );
+                }
+            }
+        }
+
+        // Checking whether any retry policy is malformed
+        for runner in self.runners.iter() {
+            if let Some(retry) = &runner.retry {
+                if retry.attempts == 0 {
+                    bail!("Runner {} allows no attempts", runner.name);
+                }
+                for pattern in retry.patterns.iter() {
+                    if let Some(e) = ere_error(pattern) {
+                        bail!(
+                            "Runner {} has invalid retry pattern {} with error: {}",
+                            runner.name,
+                            pattern,
+                            e
+                        );
+                    }
+                }
+            }
+        }
+
+        // Checking whether any early termination policy is malformed
+        for runner in self.runners.iter() {
+            if runner.give_up_after == Some(0) {
+                bail!("Runner {} gives up after 0 failures", runner.name);
+            }
+        }
+        for task in self.tasks.iter() {
+            if task.give_up_after == Some(0) {
+                bail!("Task {} gives up after 0 failures", task.name);
+            }
+        }
+
+        // Checking whether tasks have problems according to the defined runners
+        for task in self.tasks.iter() {
+            if task.learn.is_empty() && self.learner_count() > 0 {
+                bail!("Task {} has no learn problems", task.name);
+            }
+            if task.solve.is_empty() && self.solver_count() > 0 {
+                bail!("Task {} has no solve problems", task.name);
+            }
+            if task.learn.is_empty() && task.solve.is_empty() {
+                bail!("Task {} has no problems", task.name);
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Why pattern is no extended regex as understood by grep, if it is not
+/// Syntax of other flavours, such as \d or (?i), is rejected rather than
+/// silently never matching
+fn ere_error(pattern: &str) -> Option<String> {
+    if pattern.contains("(?") {
+        return Some("(? groups and flags are not supported".to_owned());
     }
+    let mut chars = pattern.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            continue;
+        }
+        match chars.next() {
+            // GNU extensions and back references
+            Some(e) if "wWsSbB<>`'123456789".contains(e) => {}
+            Some(e) if e.is_alphanumeric() => {
+                return Some(format!("\\{} is not supported", e));
+            }
+            _ => {}
+        }
+    }
+    // grep exits with 2 on syntax errors and warns about questionable ones
+    let output = Command::new("grep")
+        .args(["-E", "-e", pattern])
+        .stdin(Stdio::null())
+        .output()
+        .ok()?;
+    let stderr = String::from_utf8_lossy(&output.stderr).trim().to_owned();
+    match output.status.code() == Some(2) || !stderr.is_empty() {
+        true => Some(stderr),
+        false => None,
+    }
+}
 
-    // Checking whether any attributes are undefined
-    for runner in suite.runners.iter() {
-        if let Some(attribute) = &runner.attribute {
-            if suite.get_attribute(&attribute).is_none() {
-                panic!(
-                    "Runner {} uses undefined attribute {}",
-                    runner.name, attribute
-                );
+/// Path relative to base made absolute
+fn absolute(path: &Path, base: &Path) -> Result<PathBuf> {
+    trace!("Absolutizing path {:?}", path);
+    Ok(path.absolutize_from(base)?.to_path_buf())
+}
+
+/// Paths matching the glob patterns relative to base, without duplicates
+/// Patterns naming an existing file are taken literally
+fn expand(patterns: &[PathBuf], base: &Path) -> Result<Vec<PathBuf>> {
+    let escaped = glob::Pattern::escape(&base.to_string_lossy());
+    let mut paths: Vec<PathBuf> = vec![];
+    for pattern in patterns.iter() {
+        let path = absolute(pattern, base)?;
+        if path.is_file() {
+            if !paths.contains(&path) {
+                paths.push(path);
+            }
+            continue;
+        }
+        let pattern = pattern.to_string_lossy();
+        let absolute = match pattern.starts_with('/') {
+            true => pattern.to_string(),
+            false => format!("{}/{}", escaped, pattern),
+        };
+        let globbed =
+            glob(&absolute).map_err(|e| anyhow!("Failed to glob {} with error: {}", pattern, e))?;
+        for path in globbed.into_iter() {
+            let path = path
+                .map_err(|e| anyhow!("Globbing {} resulted in error: {}", pattern, e))?
+                .absolutize()?
+                .to_path_buf();
+            if !paths.contains(&path) {
+                paths.push(path);
             }
         }
     }
+    Ok(paths)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    const SUITE: &str = r#"
+[[runners]]
+name = "learner"
+path = "bin/learn.sh"
+kind = "Learn"
 
-    // Checking whether tasks have problems according to the defined runners
-    for task in suite.tasks.iter() {
-        if task.learn.is_empty() && suite.learner_count() > 0 {
-            panic!("Task {} has no learn problems", task.name);
+[[runners]]
+name = "solver"
+path = "bin/solve.sh"
+kind = "Solve"
+depends = "learner"
+
+[[tasks]]
+name = "t"
+domain = "t/domain.pddl"
+learn = ["t/train/*.pddl"]
+solve = ["t/p*.pddl", "t/p1.pddl"]
+"#;
+
+    fn suite_dir() -> Result<tempfile::TempDir> {
+        let dir = tempfile::tempdir()?;
+        fs::create_dir_all(dir.path().join("t/train"))?;
+        for file in ["t/domain.pddl", "t/p1.pddl", "t/p2.pddl", "t/train/a.pddl"] {
+            fs::write(dir.path().join(file), "")?;
         }
-        if task.solve.is_empty() && suite.solver_count() > 0 {
-            panic!("Task {} has no solve problems", task.name);
+        Ok(dir)
+    }
+
+    #[test]
+    fn resolves_paths_relative_to_base() -> Result<()> {
+        let dir = suite_dir()?;
+        let suite = parse(SUITE, dir.path())?;
+        suite.validate()?;
+        assert_eq!(suite.runners[0].path, dir.path().join("bin/learn.sh"));
+        let task = &suite.tasks[0];
+        assert_eq!(task.domain, dir.path().join("t/domain.pddl"));
+        assert_eq!(task.learn, [dir.path().join("t/train/a.pddl")]);
+        assert_eq!(
+            task.solve,
+            [dir.path().join("t/p1.pddl"), dir.path().join("t/p2.pddl")]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn rejects_dependencies_on_undefined_runners_and_solvers() -> Result<()> {
+        let dir = suite_dir()?;
+        let undefined = SUITE.replace("depends = \"learner\"", "depends = \"other\"");
+        assert!(parse(&undefined, dir.path())?.validate().is_err());
+        let solver = SUITE.replace("depends = \"learner\"", "depends = \"solver\"");
+        assert!(parse(&solver, dir.path())?.validate().is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn accepts_only_extended_regexes_as_retry_patterns() {
+        for pattern in [
+            "Connection (refused|reset)",
+            "^error: [0-9]+$",
+            r"\bfailed\b",
+        ] {
+            assert_eq!(ere_error(pattern), None, "{}", pattern);
         }
-        if task.learn.is_empty() && task.solve.is_empty() {
-            panic!("Task {} has no problems", task.name);
+        for pattern in [r"code \d+", "(?i)out of memory", "unbalanced (", "a{2,1}"] {
+            assert!(ere_error(pattern).is_some(), "{}", pattern);
         }
     }
 
-    info!("Runner count: {}", suite.runners.len());
-    info!("Runners: {:?}", suite.runner_names());
-    info!("Task count: {}", suite.tasks.len());
-    info!("Tasks: {:?}", suite.task_names());
-    info!("Problem learn count: {:?}", suite.total_problems_learn());
-    info!("Problem solve count: {:?}", suite.total_problems_solve());
+    #[test]
+    fn rejects_tasks_without_problems() -> Result<()> {
+        let dir = suite_dir()?;
+        let suite = parse(&SUITE.replace("t/p*.pddl", "t/q*.pddl"), dir.path())?;
+        assert!(suite.validate().is_ok());
+        let suite = parse(
+            &SUITE.replace("\"t/p*.pddl\", \"t/p1.pddl\"", "\"t/q*.pddl\""),
+            dir.path(),
+        )?;
+        assert!(suite.validate().is_err());
+        Ok(())
+    }
 
-    Ok(suite)
+    #[test]
+    fn resolving_again_keeps_resolved_paths() -> Result<()> {
+        let dir = suite_dir()?;
+        fs::write(dir.path().join("t/p[1].pddl"), "")?;
+        let mut suite = parse(&SUITE.replace("t/p1.pddl", "t/p[[]1].pddl"), dir.path())?;
+        let solve = suite.tasks[0].solve.to_owned();
+        assert!(solve.contains(&dir.path().join("t/p[1].pddl")));
+        suite.resolve(Path::new("/elsewhere"))?;
+        assert_eq!(suite.tasks[0].solve, solve);
+        assert_eq!(suite.runners[0].path, dir.path().join("bin/learn.sh"));
+        Ok(())
+    }
+
+    #[test]
+    fn runners_built_in_code_have_the_defaults_of_suite_files() -> Result<()> {
+        let dir = suite_dir()?;
+        let suite = parse(SUITE, dir.path())?;
+        let runner = Runner::new("learner", Path::new("bin/learn.sh"), RunnerKind::Learn);
+        assert_eq!(runner.give_up_on, suite.runners[0].give_up_on);
+        assert_eq!(runner.args, suite.runners[0].args);
+        Ok(())
+    }
 }
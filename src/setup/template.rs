@@ -0,0 +1,112 @@
+use anyhow::{bail, Result};
+use once_cell::sync::Lazy;
+use regex::Regex;
+use std::collections::HashMap;
+
+static PLACEHOLDER: Lazy<Regex> = Lazy::new(|| Regex::new(r"\{([a-z_.]+)\}").unwrap());
+
+/// Placeholders available to solvers
+pub const SOLVER_PLACEHOLDERS: [&str; 6] =
+    ["out", "dir", "task", "domain", "problem", "depends.dir"];
+/// Placeholders available to learners
+pub const LEARNER_PLACEHOLDERS: [&str; 5] = ["out", "dir", "task", "domain", "problems"];
+
+/// Whether any of the args contain a placeholder, in which case they are
+/// used as is rather than in the fixed positional order
+pub fn is_templated(args: &[String]) -> bool {
+    args.iter().any(|arg| PLACEHOLDER.is_match(arg))
+}
+
+/// Names of all placeholders used in args
+pub fn placeholders(args: &[String]) -> Vec<&str> {
+    args.iter()
+        .flat_map(|arg| PLACEHOLDER.captures_iter(arg))
+        .map(|c| c.get(1).unwrap().as_str())
+        .collect()
+}
+
+/// Substitutes placeholders in args with their values
+/// An arg consisting solely of a placeholder with several values is expanded
+/// into one arg per value, otherwise the values are joined by spaces
+pub fn expand(args: &[String], values: &HashMap<&str, Vec<String>>) -> Result<Vec<String>> {
+    let mut expanded = vec![];
+    for arg in args.iter() {
+        if let Some(c) = PLACEHOLDER.captures(arg) {
+            if c.get(0).unwrap().as_str() == arg {
+                match values.get(&c[1]) {
+                    Some(v) => expanded.extend(v.iter().cloned()),
+                    None => bail!("Unknown placeholder {} in arg {}", &c[0], arg),
+                }
+                continue;
+            }
+        }
+        let mut missing = None;
+        let replaced =
+            PLACEHOLDER.replace_all(arg, |c: &regex::Captures| match values.get(&c[1]) {
+                Some(v) => v.join(" "),
+                None => {
+                    missing = Some(c[0].to_owned());
+                    "".to_owned()
+                }
+            });
+        if let Some(missing) = missing {
+            bail!("Unknown placeholder {} in arg {}", missing, arg);
+        }
+        expanded.push(replaced.into_owned());
+    }
+    Ok(expanded)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(args: &[&str]) -> Vec<String> {
+        args.iter().map(|a| a.to_string()).collect()
+    }
+
+    fn values() -> HashMap<&'static str, Vec<String>> {
+        HashMap::from([
+            ("out", args(&["/w/learn/0/out"])),
+            ("domain", args(&["/s/domain.pddl"])),
+            ("problems", args(&["/s/p1.pddl", "/s/p2.pddl"])),
+        ])
+    }
+
+    #[test]
+    fn detects_templates() {
+        assert!(is_templated(&args(&["--domain", "{domain}"])));
+        assert!(is_templated(&args(&["--out={out}"])));
+        assert!(!is_templated(&args(&["astar(lmcut())", "{}"])));
+        assert_eq!(
+            placeholders(&args(&["{out}", "--x={depends.dir}/plan"])),
+            ["out", "depends.dir"]
+        );
+    }
+
+    #[test]
+    fn expands_in_place() -> Result<()> {
+        let expanded = expand(&args(&["--domain", "{domain}", "--out={out}"]), &values())?;
+        assert_eq!(
+            expanded,
+            args(&["--domain", "/s/domain.pddl", "--out=/w/learn/0/out"])
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn sole_placeholder_expands_to_several_args() -> Result<()> {
+        let expanded = expand(&args(&["{problems}", "-p={problems}"]), &values())?;
+        assert_eq!(
+            expanded,
+            args(&["/s/p1.pddl", "/s/p2.pddl", "-p=/s/p1.pddl /s/p2.pddl"])
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn rejects_unknown_placeholders() {
+        assert!(expand(&args(&["{problem}"]), &values()).is_err());
+        assert!(expand(&args(&["--x={depends.dir}"]), &values()).is_err());
+    }
+}
@@ -0,0 +1,81 @@
+use anyhow::Result;
+use labyr::evaluation::{self, Outcome};
+use labyr::execution::{self, ExecutionKind, Options};
+use labyr::misc::logging;
+use labyr::setup::instance;
+use labyr::setup::suite::{Runner, RunnerKind, Suite, Task};
+use std::env;
+use std::fs;
+use std::os::unix::fs::PermissionsExt;
+use std::path::Path;
+
+fn write_executable(path: &Path, content: &str) -> Result<()> {
+    fs::write(path, content)?;
+    fs::set_permissions(path, fs::Permissions::from_mode(0o755))?;
+    Ok(())
+}
+
+#[test]
+fn suites_built_in_code_are_generated_executed_and_evaluated() -> Result<()> {
+    env::set_var("RUST_LOG", "warn");
+    logging::init();
+    let dir = tempfile::tempdir()?;
+    let base = dir.path();
+    for file in ["domain.pddl", "p1.pddl", "p2.pddl"] {
+        fs::write(base.join(file), "")?;
+    }
+    write_executable(&base.join("learn.sh"), "#!/bin/bash\ntouch learned\n")?;
+    // Solves the problems its learner has learned from
+    write_executable(
+        &base.join("solve.sh"),
+        "#!/bin/bash\n[ -f \"$1/learned\" ] && [[ \"$3\" == */p1.pddl ]]\n",
+    )?;
+
+    let suite = Suite {
+        runners: vec![
+            Runner::new("learner", &base.join("learn.sh"), RunnerKind::Learn),
+            Runner {
+                args: vec![
+                    "{depends.dir}".to_owned(),
+                    "{domain}".to_owned(),
+                    "{problem}".to_owned(),
+                ],
+                depends: Some("learner".to_owned()),
+                ..Runner::new("solver", &base.join("solve.sh"), RunnerKind::Solve)
+            },
+        ],
+        tasks: vec![Task {
+            learn: vec![base.join("p1.pddl")],
+            solve: vec![base.join("p*.pddl")],
+            ..Task::new("t", &base.join("domain.pddl"))
+        }],
+        ..Default::default()
+    };
+    let instance = instance::generate(&base.join("work"), suite, &Default::default())?;
+    assert_eq!(instance.runs.len(), 3);
+    execution::execute(
+        instance.to_owned(),
+        ExecutionKind::Local,
+        &Options {
+            threads: 2,
+            ..Default::default()
+        },
+    )?;
+
+    let results = evaluation::results(&instance);
+    assert_eq!(results.learn.len(), 1);
+    assert_eq!(results.learn[0].exit_code, Some(0));
+    let solve: Vec<(&str, Option<i32>, Outcome)> = results
+        .solve
+        .iter()
+        .map(|r| (r.problem.as_str(), r.exit_code, r.outcome))
+        .collect();
+    assert_eq!(
+        solve,
+        [
+            ("p1", Some(0), Outcome::Finished),
+            ("p2", Some(1), Outcome::Finished)
+        ]
+    );
+    Ok(())
+}
@@ -0,0 +1,305 @@
+use anyhow::Result;
+use labyr::execution::{self, slurm, ExecutionKind, Options, SlurmExecutor};
+use labyr::misc::logging;
+use labyr::setup::{self, instance::Instance};
+use std::env;
+use std::fs;
+use std::os::unix::fs::PermissionsExt;
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, MutexGuard, Once, OnceLock};
+use std::time::Duration;
+
+/// Runs array elements right away and hands out increasing job ids, logging
+/// the options and index map of each submission
+const SBATCH: &str = r#"#!/bin/bash
+DIR=$(dirname "$0")
+ARGS=("$@")
+MAP="${ARGS[-1]}"
+echo "${ARGS[*]:0:$#-2} $(basename "$(dirname "$MAP")")/$(basename "$MAP")" >> "$DIR/sbatch.log"
+RANGE=$(echo "$@" | sed -E 's/.*--array=0-([0-9]+).*/\1/')
+for i in $(seq 0 $RANGE); do
+    SLURM_ARRAY_TASK_ID=$i bash "${ARGS[-2]}" "${ARGS[-1]}" > /dev/null
+done
+ID=$(( $(cat "$DIR/counter" 2>/dev/null || echo 100) + 1 ))
+echo $ID > "$DIR/counter"
+echo "$ID;cluster"
+"#;
+
+/// Prints the accounting records the test put in place, failing without any
+/// and for as many calls as sacct.failures gives
+const SACCT: &str = r#"#!/bin/bash
+DIR=$(dirname "$0")
+FAILURES=$(cat "$DIR/sacct.failures" 2>/dev/null || echo 0)
+if [ ! -f "$DIR/sacct.out" ] || [ "$FAILURES" -gt 0 ]; then
+    echo $((FAILURES - 1)) > "$DIR/sacct.failures"
+    echo "slurmdbd unreachable" >&2
+    exit 1
+fi
+cat "$DIR/sacct.out"
+"#;
+
+/// Prints the queue the test put in place, failing without any
+const SQUEUE: &str = r#"#!/bin/bash
+DIR=$(dirname "$0")
+[ -f "$DIR/squeue.out" ] || { echo "Unable to contact slurm controller" >&2; exit 1; }
+cat "$DIR/squeue.out"
+"#;
+
+/// Logs the jobs it is asked to cancel
+const SCANCEL: &str = r#"#!/bin/bash
+echo "$@" >> "$(dirname "$0")/scancel.log"
+"#;
+
+const SUITE: &str = r#"
+[[runners]]
+name = "solver"
+path = "solver.sh"
+kind = "Solve"
+
+[[tasks]]
+name = "t"
+domain = "domain.pddl"
+solve = ["p1.pddl", "p2.pddl"]
+"#;
+
+/// Tests put their stubs on PATH, so they take turns
+static LOCK: Mutex<()> = Mutex::new(());
+static PATH: OnceLock<String> = OnceLock::new();
+static LOGGING: Once = Once::new();
+
+fn write_executable(path: &Path, content: &str) -> Result<()> {
+    fs::write(path, content)?;
+    fs::set_permissions(path, fs::Permissions::from_mode(0o755))?;
+    Ok(())
+}
+
+/// Puts stubs of the slurm commands in dir first on PATH, held until the
+/// guard is dropped
+fn stubs(dir: &Path) -> Result<(PathBuf, MutexGuard<'static, ()>)> {
+    let guard = LOCK.lock().unwrap_or_else(|e| e.into_inner());
+    // Progress is reported through logging, kept to warnings
+    LOGGING.call_once(|| {
+        env::set_var("RUST_LOG", "warn");
+        logging::init();
+    });
+    let path = PATH.get_or_init(|| env::var("PATH").unwrap_or_default());
+    let stubs = dir.join("stubs");
+    fs::create_dir(&stubs)?;
+    write_executable(&stubs.join("sbatch"), SBATCH)?;
+    write_executable(&stubs.join("sacct"), SACCT)?;
+    write_executable(&stubs.join("squeue"), SQUEUE)?;
+    write_executable(&stubs.join("scancel"), SCANCEL)?;
+    env::set_var("PATH", format!("{}:{}", stubs.to_string_lossy(), path));
+    Ok((stubs, guard))
+}
+
+/// Instance of suite in dir, with empty problems and runners that succeed
+fn instance(dir: &Path, suite: &str) -> Result<(PathBuf, Instance)> {
+    let suite_dir = dir.join("suite");
+    fs::create_dir(&suite_dir)?;
+    for file in [
+        "domain.pddl",
+        "p1.pddl",
+        "p2.pddl",
+        "p3.pddl",
+        "p4.pddl",
+        "p5.pddl",
+    ] {
+        fs::write(suite_dir.join(file), "")?;
+    }
+    for runner in ["learner.sh", "solver.sh"] {
+        write_executable(&suite_dir.join(runner), "#!/bin/bash\nexit 0\n")?;
+    }
+    fs::write(suite_dir.join("suite.toml"), suite)?;
+    let work_dir = dir.join("work");
+    fs::create_dir(&work_dir)?;
+    let instance = setup::run(&work_dir, &suite_dir.join("suite.toml"), false, false)?;
+    Ok((work_dir, instance))
+}
+
+/// Submits instance without waiting on its jobs, returning the logged
+/// submissions
+fn submit(instance: &Instance, stubs: &Path) -> Result<Vec<String>> {
+    execution::execute(
+        instance.to_owned(),
+        ExecutionKind::Slurm,
+        &Options {
+            detach: true,
+            ..Default::default()
+        },
+    )?;
+    Ok(fs::read_to_string(stubs.join("sbatch.log"))?
+        .lines()
+        .map(|l| l.to_owned())
+        .collect())
+}
+
+/// Run dirs listed by an index map
+fn index_map(path: &Path) -> Result<Vec<PathBuf>> {
+    Ok(fs::read_to_string(path)?
+        .lines()
+        .map(PathBuf::from)
+        .collect())
+}
+
+#[test]
+fn detached_jobs_are_collected_once_sacct_reports_them_done() -> Result<()> {
+    let dir = tempfile::tempdir()?;
+    let (stubs, _guard) = stubs(dir.path())?;
+    let (work_dir, instance) = instance(dir.path(), SUITE)?;
+    submit(&instance, &stubs)?;
+    assert_eq!(
+        fs::read_to_string(work_dir.join(slurm::JOBS_FILE))?,
+        "101\n"
+    );
+    assert!(instance
+        .runs
+        .iter()
+        .all(|r| r.dir.join("exit_code").exists()));
+
+    // Neither accounting nor the queue is available
+    assert!(slurm::collect(&work_dir).is_err());
+    // Jobs are not known to accounting yet
+    fs::write(stubs.join("sacct.out"), "")?;
+    assert!(!slurm::collect(&work_dir)?);
+    fs::write(stubs.join("sacct.out"), "101_[0-1]|PENDING\n")?;
+    assert!(!slurm::collect(&work_dir)?);
+    fs::write(
+        stubs.join("sacct.out"),
+        "101_0|COMPLETED\n101_0.batch|COMPLETED\n101_1|RUNNING\n",
+    )?;
+    assert!(!slurm::collect(&work_dir)?);
+    fs::write(
+        stubs.join("sacct.out"),
+        "101_0|COMPLETED\n101_0.batch|COMPLETED\n101_1|CANCELLED by 1000\n",
+    )?;
+    assert!(slurm::collect(&work_dir)?);
+    Ok(())
+}
+
+#[test]
+fn arrays_are_split_by_max_array_size_and_throttled() -> Result<()> {
+    let dir = tempfile::tempdir()?;
+    let (stubs, _guard) = stubs(dir.path())?;
+    let suite = SUITE.replace("\"p1.pddl\", \"p2.pddl\"", "\"p*.pddl\"")
+        + "[slurm]\nmax_array_size = 2\nthrottle = 3\n";
+    let (_, instance) = instance(dir.path(), &suite)?;
+    assert_eq!(
+        submit(&instance, &stubs)?,
+        [
+            "--parsable --array=0-1%3 solve/array.0",
+            "--parsable --array=0-1%3 solve/array.1",
+            "--parsable --array=0-0%3 solve/array.2",
+        ]
+    );
+    let dirs: Vec<PathBuf> = instance.runs.iter().map(|r| r.dir.to_owned()).collect();
+    assert_eq!(index_map(&instance.solve_dir.join("array.0"))?, dirs[0..2]);
+    assert_eq!(index_map(&instance.solve_dir.join("array.1"))?, dirs[2..4]);
+    assert_eq!(index_map(&instance.solve_dir.join("array.2"))?, dirs[4..5]);
+    Ok(())
+}
+
+#[test]
+fn solve_arrays_depend_on_the_element_of_their_learner() -> Result<()> {
+    let dir = tempfile::tempdir()?;
+    let (stubs, _guard) = stubs(dir.path())?;
+    let mut suite = r#"
+[[runners]]
+name = "learner"
+path = "learner.sh"
+kind = "Learn"
+
+[[runners]]
+name = "solver"
+path = "solver.sh"
+kind = "Solve"
+depends = "learner"
+
+[slurm.learn]
+runs_per_job = 2
+
+[slurm.solve]
+max_array_size = 2
+runs_per_job = 2
+"#
+    .to_owned();
+    for task in ["t1", "t2", "t3"] {
+        suite += &format!(
+            "[[tasks]]\nname = \"{}\"\ndomain = \"domain.pddl\"\n\
+             learn = [\"p1.pddl\"]\nsolve = [\"p1.pddl\", \"p2.pddl\", \"p3.pddl\"]\n",
+            task
+        );
+    }
+    let (_, instance) = instance(dir.path(), &suite)?;
+    // The learners of t1 and t2 share element 101_0, that of t3 is 101_1
+    assert_eq!(
+        submit(&instance, &stubs)?,
+        [
+            "--parsable --array=0-1 learn/array.0",
+            "--parsable --array=0-1 --dependency=afterany:101_0 solve/array.0",
+            "--parsable --array=0-0 --dependency=afterany:101_0 solve/array.1",
+            "--parsable --array=0-1 --dependency=afterany:101_1 solve/array.2",
+        ]
+    );
+    let dirs: Vec<PathBuf> = instance.runs.iter().map(|r| r.dir.to_owned()).collect();
+    assert_eq!(index_map(&instance.learn_dir.join("array.0"))?, dirs[0..3]);
+    assert_eq!(index_map(&instance.solve_dir.join("array.0"))?, dirs[3..7]);
+    assert_eq!(index_map(&instance.solve_dir.join("array.1"))?, dirs[7..9]);
+    assert_eq!(index_map(&instance.solve_dir.join("array.2"))?, dirs[9..12]);
+    Ok(())
+}
+
+#[test]
+fn jobs_are_not_cancelled_when_checking_on_them_fails() -> Result<()> {
+    let dir = tempfile::tempdir()?;
+    let (stubs, _guard) = stubs(dir.path())?;
+    let (_, instance) = instance(dir.path(), SUITE)?;
+    fs::write(stubs.join("sacct.failures"), "1")?;
+    fs::write(
+        stubs.join("sacct.out"),
+        "101_0|COMPLETED\n101_1|COMPLETED\n",
+    )?;
+    execution::run(
+        &instance,
+        &mut SlurmExecutor::new(Duration::ZERO),
+        &Options::default(),
+    )?;
+    assert_eq!(fs::read_to_string(stubs.join("sacct.failures"))?, "0\n");
+    assert!(!stubs.join("scancel.log").exists());
+    Ok(())
+}
+
+#[test]
+fn jobs_are_checked_on_with_squeue_without_accounting() -> Result<()> {
+    let dir = tempfile::tempdir()?;
+    let (stubs, _guard) = stubs(dir.path())?;
+    let (work_dir, instance) = instance(dir.path(), SUITE)?;
+    submit(&instance, &stubs)?;
+    fs::write(stubs.join("squeue.out"), "101_1|RUNNING\n")?;
+    assert!(!slurm::collect(&work_dir)?);
+    fs::write(stubs.join("squeue.out"), "")?;
+    assert!(slurm::collect(&work_dir)?);
+    execution::run(
+        &instance,
+        &mut SlurmExecutor::new(Duration::ZERO),
+        &Options::default(),
+    )?;
+    Ok(())
+}
+
+#[test]
+fn packed_runs_are_executed_in_dirs_with_spaces() -> Result<()> {
+    let dir = tempfile::tempdir()?;
+    let dir = dir.path().join("with space");
+    fs::create_dir(&dir)?;
+    let (stubs, _guard) = stubs(&dir)?;
+    let suite =
+        SUITE.replace("\"p1.pddl\", \"p2.pddl\"", "\"p*.pddl\"") + "[slurm]\nruns_per_job = 2\n";
+    let (_, instance) = instance(&dir, &suite)?;
+    submit(&instance, &stubs)?;
+    assert!(instance
+        .runs
+        .iter()
+        .all(|r| r.dir.join("exit_code").exists()));
+    Ok(())
+}